@@ -0,0 +1,742 @@
+// Headless frontend: mcm status / ps / trim / affected / clean / watch / metrics / export / config
+
+use memory_cache_core::clean::{CleanPlan, Strategy};
+use memory_cache_core::config::ConfigStore;
+use memory_cache_core::export::{self, Dataset, ExportFormat, ExportOptions, Unit};
+use memory_cache_core::exporter::{self, MetricsServer};
+use memory_cache_core::history::{self, CleanMarker, CleanStats, HistoryRange};
+use memory_cache_core::metrics_store::{MetricsStore, StoreOptions};
+use memory_cache_core::process::trim::{self as process_trim, trim_processes};
+use memory_cache_core::process::{
+    self, ProcessDecision, ProcessQuery, ProcessSource, ProcessTarget, ProcfsProcesses, SortKey,
+    TrimMethod,
+};
+use memory_cache_core::{clean, source, Config, Error, MemoryInfo, MemorySource, Result};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: mcm [--source <spec>] [--config <file>] <command>
+
+Commands:
+  status [--json]               Print current memory usage
+  ps [--sort rss|pss|uss|swap|private|pid|name] [--reverse]
+     [--filter <text>] [--min-rss-mb <mb>] [--limit <n>]
+     [--proc-root <dir>] [--json]
+                                List per-process memory (default: top 20
+                                by RSS)
+  trim <pid|name pattern> [--method native|cgroup-reclaim] [--dry-run]
+       [--json]                 Page out other processes' memory (default:
+                                process_madvise on Linux, EmptyWorkingSet
+                                on Windows), skipping processes the
+                                process_allow/process_deny rules exclude
+  affected [--strategy <spec>] [--json]
+                                List the processes a clean would act on and
+                                which of them the rules exclude
+  clean [--target-mb <mb>] [--strategy <spec>] [--dry-run] [--json]
+                                Clean memory cache (default: the gap between
+                                the thresholds, configured strategy);
+                                --dry-run prints the plan and what would
+                                stop it without touching memory; a real
+                                clean is added to the recorded history
+  watch [--interval <secs>] [--json]
+                                Print memory usage until interrupted
+  metrics [--port <port>]       Print Prometheus metrics, or serve them on
+                                127.0.0.1:<port>/metrics until interrupted
+  export [--range <secs>] [--format csv|jsonl|json]
+         [--dataset samples|cleans|all] [--unit bytes|kb|mb|gb]
+         [--columns <a,b,...>] [--dir <metrics dir>] [--output <file>]
+                                Export recorded history (default: the last
+                                24 hours of samples as CSV in MB)
+  config get [<key>]            Print the whole config or one key
+  config set <key> <value>      Change one config key
+
+Options:
+  --source <spec>   native, procfs[:<file>], cgroup:<dir> or scripted:<file>
+                    (default: $MCM_MEMORY_SOURCE or native)
+  --config <file>   Config file (default: the GUI's file in the platform
+                    config directory)
+
+Strategies:
+  alloc-pressure[:floor=<mb>][:max=<percent>]
+                                     Windows allocate-and-free, stopping
+                                     before available memory drops to
+                                     the floor (512) or more than max% of
+                                     RAM (50) is cycled
+  trim-working-set                   Windows EmptyWorkingSet on mcm itself
+  drop-caches[:<1|2|3>][:no-sync]    Linux drop_caches (1 page cache,
+                                     2 dentries/inodes, 3 both)
+  cgroup-reclaim:<cgroup dir>        Linux cgroup v2 memory.reclaim of
+                                     --target-mb from one cgroup
+  <strategy>[@<stop>],<strategy>...  Pipeline: run the strategies in order;
+                                     after a step, @target stops once
+                                     --target-mb is freed and
+                                     @stop-threshold once the stop
+                                     threshold is reached, e.g.
+                                     drop-caches:1@stop-threshold,drop-caches:3
+                                     (Windows default:
+                                     alloc-pressure,trim-working-set)";
+
+struct Args {
+    source: Option<String>,
+    config: Option<PathBuf>,
+    command: Vec<String>,
+}
+
+impl Args {
+    fn parse(mut raw: impl Iterator<Item = String>) -> Result<Self> {
+        let mut source = None;
+        let mut config = None;
+        let mut command = Vec::new();
+
+        while let Some(arg) = raw.next() {
+            match arg.as_str() {
+                "--source" => source = Some(value_of(&arg, raw.next())?),
+                "--config" => config = Some(PathBuf::from(value_of(&arg, raw.next())?)),
+                _ => command.push(arg),
+            }
+        }
+
+        Ok(Self {
+            source,
+            config,
+            command,
+        })
+    }
+
+    fn memory_source(&self) -> Result<Box<dyn MemorySource>> {
+        match &self.source {
+            Some(spec) => source::from_spec(spec),
+            None => source::default_source(),
+        }
+    }
+
+    fn config_store(&self) -> Result<ConfigStore> {
+        match &self.config {
+            Some(path) => Ok(ConfigStore::new(path)),
+            None => ConfigStore::default_location(),
+        }
+    }
+}
+
+fn value_of(flag: &str, value: Option<String>) -> Result<String> {
+    value.ok_or_else(|| Error::invalid_input(format!("{} needs a value", flag)))
+}
+
+/// Pull `--flag <value>` out of a command's arguments.
+fn take_option(args: &mut Vec<String>, flag: &str) -> Result<Option<String>> {
+    match args.iter().position(|a| a == flag) {
+        Some(i) => {
+            args.remove(i);
+            if i < args.len() {
+                Ok(Some(args.remove(i)))
+            } else {
+                Err(Error::invalid_input(format!("{} needs a value", flag)))
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+/// Pull a bare `--flag` out of a command's arguments.
+fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    match args.iter().position(|a| a == flag) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+fn parse_number(flag: &str, value: &str) -> Result<u64> {
+    value
+        .parse()
+        .map_err(|_| Error::invalid_input(format!("Invalid value for {}: {}", flag, value)))
+}
+
+fn no_extra_args(args: &[String]) -> Result<()> {
+    match args.first() {
+        Some(arg) => Err(Error::invalid_input(format!(
+            "Unexpected argument: {}",
+            arg
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn optional_mb(value: Option<u64>) -> String {
+    value.map_or_else(|| "-".to_string(), |mb| format!("{} MB", mb))
+}
+
+fn print_table(info: &MemoryInfo) {
+    let rows = [
+        ("Total", format!("{} MB", info.total_mb)),
+        ("Used", format!("{} MB", info.used_mb)),
+        ("Available", format!("{} MB", info.available_mb)),
+        ("Usage", format!("{:.1}%", info.usage_percent)),
+        ("Cache", format!("{} MB", info.cache_mb)),
+        ("  Page cache", format!("{} MB", info.page_cache_mb)),
+        ("  Buffers", optional_mb(info.buffers_mb)),
+        ("  Reclaimable slab", optional_mb(info.reclaimable_slab_mb)),
+        ("  Standby list", optional_mb(info.standby_mb)),
+        ("  Modified list", optional_mb(info.modified_mb)),
+        ("Shared / tmpfs", optional_mb(info.shared_mb)),
+    ];
+    for (label, value) in rows {
+        println!("{:<20} {:>12}", label, value);
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String> {
+    serde_json::to_string(value).map_err(|e| Error::invalid_input(e.to_string()))
+}
+
+fn status(args: &Args, mut rest: Vec<String>) -> Result<()> {
+    let json = take_flag(&mut rest, "--json");
+    no_extra_args(&rest)?;
+
+    let info = args.memory_source()?.read()?;
+    if json {
+        println!("{}", to_json(&info)?);
+    } else {
+        print_table(&info);
+    }
+    Ok(())
+}
+
+fn optional_kb(value: Option<u64>) -> String {
+    value.map_or_else(|| "-".to_string(), |kb| format!("{}", kb / 1024))
+}
+
+fn ps(mut rest: Vec<String>) -> Result<()> {
+    let sort = take_option(&mut rest, "--sort")?;
+    let reverse = take_flag(&mut rest, "--reverse");
+    let filter = take_option(&mut rest, "--filter")?;
+    let min_rss_mb = take_option(&mut rest, "--min-rss-mb")?;
+    let limit = take_option(&mut rest, "--limit")?;
+    let proc_root = take_option(&mut rest, "--proc-root")?;
+    let json = take_flag(&mut rest, "--json");
+    no_extra_args(&rest)?;
+
+    let query = ProcessQuery {
+        sort: sort
+            .as_deref()
+            .map(SortKey::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        reverse,
+        filter,
+        min_rss_kb: match min_rss_mb {
+            Some(value) => parse_number("--min-rss-mb", &value)? * 1024,
+            None => 0,
+        },
+        limit: match limit {
+            Some(value) => Some(parse_number("--limit", &value)? as usize),
+            None => Some(20),
+        },
+    };
+    let source: Box<dyn ProcessSource> = match proc_root {
+        Some(root) => Box::new(ProcfsProcesses::with_root(root)),
+        None => process::native_processes(),
+    };
+    let processes = process::list_processes(&*source, &query)?;
+
+    if json {
+        println!("{}", to_json(&processes)?);
+        return Ok(());
+    }
+    println!(
+        "{:>7} {:>8} {:>8} {:>8} {:>8} {:>8}  NAME",
+        "PID", "RSS MB", "PSS MB", "USS MB", "SWAP MB", "PRIV MB"
+    );
+    for p in &processes {
+        println!(
+            "{:>7} {:>8} {:>8} {:>8} {:>8} {:>8}  {}",
+            p.pid,
+            p.rss_kb / 1024,
+            optional_kb(p.pss_kb),
+            optional_kb(p.uss_kb),
+            optional_kb(p.swap_kb),
+            optional_kb(p.private_kb),
+            p.cmdline.as_deref().unwrap_or(&p.name)
+        );
+    }
+    Ok(())
+}
+
+fn print_decisions(decisions: &[ProcessDecision]) {
+    println!("{:>7} {:>8}  {:<24} EXCLUDED", "PID", "RSS MB", "NAME");
+    for d in decisions {
+        println!(
+            "{:>7} {:>8}  {:<24} {}",
+            d.process.pid,
+            d.process.rss_kb / 1024,
+            d.process.name,
+            d.excluded.as_deref().unwrap_or("-")
+        );
+    }
+}
+
+fn trim(args: &Args, mut rest: Vec<String>) -> Result<()> {
+    let method = take_option(&mut rest, "--method")?;
+    let dry_run = take_flag(&mut rest, "--dry-run");
+    let json = take_flag(&mut rest, "--json");
+    let target = match rest.len() {
+        1 => ProcessTarget::parse(&rest.remove(0)),
+        0 => return Err(Error::invalid_input("trim needs a pid or name pattern")),
+        _ => return no_extra_args(&rest[1..]),
+    };
+    let method = method
+        .as_deref()
+        .map(TrimMethod::parse)
+        .transpose()?
+        .unwrap_or_default();
+
+    let rules = args.config_store()?.load()?.process_rules;
+    let processes = process::native_processes();
+
+    if dry_run {
+        let decisions = process_trim::preview(&*processes, &target, method, &rules)?;
+        if json {
+            println!("{}", to_json(&decisions)?);
+        } else {
+            print_decisions(&decisions);
+        }
+        return Ok(());
+    }
+
+    let report = trim_processes(&*processes, &target, method, &rules)?;
+    if json {
+        println!("{}", to_json(&report)?);
+        return Ok(());
+    }
+    println!("{:>7} {:>10} {:>10}  NAME", "PID", "BEFORE MB", "AFTER MB");
+    for p in &report.processes {
+        let after = p
+            .rss_after_kb
+            .map_or_else(|| "exited".to_string(), |kb| (kb / 1024).to_string());
+        println!(
+            "{:>7} {:>10} {:>10}  {}",
+            p.pid,
+            p.rss_before_kb / 1024,
+            after,
+            p.name
+        );
+        if let Some(e) = &p.error {
+            eprintln!("warning: {} ({}): {}", p.name, p.pid, e);
+        }
+    }
+    for d in &report.excluded {
+        eprintln!(
+            "skipped: {} ({}): {}",
+            d.process.name,
+            d.process.pid,
+            d.excluded.as_deref().unwrap_or_default()
+        );
+    }
+    println!("Trimmed {} MB", report.trimmed_kb() / 1024);
+    Ok(())
+}
+
+fn affected(args: &Args, mut rest: Vec<String>) -> Result<()> {
+    let strategy = take_option(&mut rest, "--strategy")?;
+    let json = take_flag(&mut rest, "--json");
+    no_extra_args(&rest)?;
+
+    let config = args.config_store()?.load()?;
+    let strategy = match strategy {
+        Some(spec) => Strategy::parse(&spec)?,
+        None => config.strategy,
+    };
+    let decisions = clean::affected_processes(
+        &strategy,
+        &config.process_rules,
+        &*process::native_processes(),
+    )?;
+
+    if json {
+        println!("{}", to_json(&decisions)?);
+    } else if decisions.is_empty() {
+        println!("{} does not act on individual processes", strategy);
+    } else {
+        print_decisions(&decisions);
+    }
+    Ok(())
+}
+
+fn print_plan(plan: &CleanPlan) {
+    println!(
+        "Dry run: {}, target {} MB, estimated {} MB freed",
+        plan.strategy, plan.target_mb, plan.estimated_mb
+    );
+    println!(
+        "  cache {} MB, available {} MB; automatic cleaning {}",
+        plan.memory.cache_mb,
+        plan.memory.available_mb,
+        if plan.evaluation.should_start {
+            "would start now"
+        } else {
+            "would not start yet"
+        }
+    );
+    if plan.requires_privilege {
+        println!("  Needs root or write access to a system file");
+    }
+    if !plan.capabilities.targeted {
+        println!("  Frees what it can reach; the target does not limit it");
+    }
+    if plan.affected.is_empty() {
+        println!("  Acts on no individual processes");
+    } else {
+        println!("  Acts on {} processes:", plan.affected.len());
+        print_decisions(&plan.affected);
+    }
+    if plan.would_run() {
+        println!("Nothing would stop this clean");
+    } else {
+        println!("This clean would not run:");
+        for blocker in &plan.blockers {
+            println!("  {}", blocker);
+        }
+    }
+}
+
+fn clean(args: &Args, mut rest: Vec<String>) -> Result<()> {
+    let target_mb = take_option(&mut rest, "--target-mb")?;
+    let strategy = take_option(&mut rest, "--strategy")?;
+    let dry_run = take_flag(&mut rest, "--dry-run");
+    let json = take_flag(&mut rest, "--json");
+    no_extra_args(&rest)?;
+
+    let config = args.config_store()?.load()?;
+    let source = args.memory_source()?;
+    let target_mb = match target_mb {
+        Some(value) => parse_number("--target-mb", &value)?,
+        None => config.band_mb(source.read()?.total_mb),
+    };
+    let strategy = match strategy {
+        Some(spec) => Strategy::parse(&spec)?,
+        None => config.strategy.clone(),
+    };
+
+    if dry_run {
+        let plan = clean::plan_clean(
+            &strategy,
+            target_mb,
+            &config,
+            &*source,
+            &*process::native_processes(),
+        )?;
+        if json {
+            println!("{}", to_json(&plan)?);
+        } else {
+            print_plan(&plan);
+        }
+        return Ok(());
+    }
+
+    clean::check_process_rules(
+        &strategy,
+        &config.process_rules,
+        &*process::native_processes(),
+    )?;
+    let report = clean::clean_memory_cache(&strategy, target_mb, &config, &*source)?;
+    // Log it next to the GUI's cleans so `mcm export --dataset cleans` sees it
+    let marker = CleanMarker {
+        timestamp_ms: history::now_ms(),
+        cleaned_mb: report.cleaned_mb,
+        automatic: false,
+        duration_ms: report.duration_ms,
+    };
+    if let Err(e) =
+        MetricsStore::default_location().and_then(|mut store| store.append_clean(&marker))
+    {
+        eprintln!("warning: recording the clean failed: {}", e);
+    }
+    if json {
+        println!("{}", to_json(&report)?);
+        return Ok(());
+    }
+
+    println!(
+        "Cleaned {} MB of memory cache ({}, {} ms)",
+        report.cleaned_mb, report.strategy, report.duration_ms
+    );
+    println!(
+        "  {:<14} {:>10} {:>10} {:>10}",
+        "", "before", "after", "delta"
+    );
+    let rows = [
+        (
+            "cache",
+            report.before.cache_mb,
+            report.after.cache_mb,
+            report.delta.cache_mb,
+        ),
+        (
+            "available",
+            report.before.available_mb,
+            report.after.available_mb,
+            report.delta.available_mb,
+        ),
+        (
+            "used",
+            report.before.used_mb,
+            report.after.used_mb,
+            report.delta.used_mb,
+        ),
+    ];
+    for (name, before, after, delta) in rows {
+        println!(
+            "  {:<14} {:>7} MB {:>7} MB {:>+7} MB",
+            name, before, after, delta
+        );
+    }
+    if let Some(delta) = report.delta.working_set_mb {
+        println!(
+            "  {:<14} {:>10} {:>10} {:>+7} MB",
+            "working set", "", "", delta
+        );
+    }
+    if let Some(reason) = &report.stop_reason {
+        println!("Stopped after {} MB: {}", report.processed_mb, reason);
+    }
+    for step in &report.steps {
+        let status = match (&step.error, step.stopped) {
+            (Some(_), _) => "failed",
+            (None, true) => "stop condition met",
+            (None, false) => "done",
+        };
+        println!(
+            "  step {:<40} {:>7} MB freed  {}",
+            step.strategy.to_string(),
+            step.cleaned_mb,
+            status
+        );
+    }
+    for step in &report.errors {
+        eprintln!("warning: {} failed: {}", step.step, step.error);
+    }
+
+    if let Some(cgroup) = &report.cgroup {
+        if !cgroup.completed {
+            println!(
+                "Kernel reclaimed less than the requested {} bytes",
+                cgroup.requested_bytes
+            );
+        }
+        println!(
+            "memory.current: {} -> {} bytes",
+            cgroup.current_before, cgroup.current_after
+        );
+        for (key, delta) in &cgroup.stat_deltas {
+            println!("  {:<28} {:>+16}", key, delta);
+        }
+    }
+    Ok(())
+}
+
+fn watch(args: &Args, mut rest: Vec<String>) -> Result<()> {
+    let interval = take_option(&mut rest, "--interval")?;
+    let json = take_flag(&mut rest, "--json");
+    no_extra_args(&rest)?;
+
+    let interval = match interval {
+        Some(value) => parse_number("--interval", &value)?.max(1),
+        None => 3,
+    };
+    let source = args.memory_source()?;
+
+    loop {
+        match source.read() {
+            Ok(info) if json => println!("{}", to_json(&info)?),
+            Ok(info) => println!(
+                "used {:>7} MB  available {:>7} MB  cache {:>7} MB  {:>5.1}%",
+                info.used_mb, info.available_mb, info.cache_mb, info.usage_percent
+            ),
+            Err(e) => eprintln!("error: {}", e),
+        }
+        std::thread::sleep(Duration::from_secs(interval));
+    }
+}
+
+fn metrics(args: &Args, mut rest: Vec<String>) -> Result<()> {
+    let port = take_option(&mut rest, "--port")?;
+    no_extra_args(&rest)?;
+
+    let config = args.config_store()?.load()?;
+    let source = args.memory_source()?;
+    let port = match port {
+        Some(value) => u16::try_from(parse_number("--port", &value)?)
+            .ok()
+            .filter(|&port| port > 0)
+            .ok_or_else(|| Error::invalid_input(format!("Invalid --port: {}", value)))?,
+        None => {
+            let info = source.read().ok();
+            print!(
+                "{}",
+                exporter::render(info.as_ref(), &config, &CleanStats::default())
+            );
+            return Ok(());
+        }
+    };
+
+    // No cleans run here, so the clean counters stay at zero
+    let source: std::sync::Arc<dyn MemorySource> = source.into();
+    let server = MetricsServer::start(
+        port,
+        move || {
+            let info = source.read().ok();
+            exporter::render(info.as_ref(), &config, &CleanStats::default())
+        },
+        |e| eprintln!("{}", e),
+    )?;
+    eprintln!("Serving metrics on http://{}/metrics", server.addr());
+    loop {
+        std::thread::park();
+    }
+}
+
+fn export(mut rest: Vec<String>) -> Result<()> {
+    let range = take_option(&mut rest, "--range")?;
+    let format = take_option(&mut rest, "--format")?;
+    let dataset = take_option(&mut rest, "--dataset")?;
+    let unit = take_option(&mut rest, "--unit")?;
+    let columns = take_option(&mut rest, "--columns")?;
+    let dir = take_option(&mut rest, "--dir")?;
+    let output = take_option(&mut rest, "--output")?;
+    no_extra_args(&rest)?;
+
+    let options = ExportOptions {
+        format: format
+            .as_deref()
+            .map(ExportFormat::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        dataset: dataset
+            .as_deref()
+            .map(Dataset::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        unit: unit
+            .as_deref()
+            .map(Unit::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        columns: columns.map(|c| c.split(',').map(|c| c.trim().to_string()).collect()),
+    };
+    let range_ms = match range {
+        Some(value) => parse_number("--range", &value)?.saturating_mul(1000),
+        None => 24 * 60 * 60 * 1000,
+    };
+
+    // Read straight from the files the GUI writes; the CLI keeps no history
+    let store = match dir {
+        Some(dir) => MetricsStore::open(dir, StoreOptions::default())?,
+        None => MetricsStore::default_location()?,
+    };
+    let to_ms = history::now_ms() + 1;
+    let (samples, cleans) = store.read_range(to_ms.saturating_sub(range_ms), to_ms)?;
+    let contents = export::export(
+        &HistoryRange {
+            resolution_ms: 0,
+            samples,
+            cleans,
+        },
+        &options,
+    )?;
+
+    match output {
+        Some(path) => std::fs::write(&path, contents)
+            .map_err(|e| Error::io(format!("Failed to write {}", path), e)),
+        None => {
+            print!("{}", contents);
+            Ok(())
+        }
+    }
+}
+
+fn config(args: &Args, rest: Vec<String>) -> Result<()> {
+    let store = args.config_store()?;
+    let mut config = store.load()?;
+
+    match rest
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["get"] => {
+            for key in Config::KEYS {
+                println!("{} = {}", key, config.get(key)?);
+            }
+        }
+        ["get", key] => println!("{}", config.get(key)?),
+        ["set", key, value] => {
+            config.set(key, value)?;
+            let total_mb = args.memory_source()?.read().ok().map(|info| info.total_mb);
+            config.validate(total_mb)?;
+            store.save(&config)?;
+        }
+        _ => {
+            return Err(Error::invalid_input(format!(
+                "Usage: mcm config get [<key>] | set <key> <value>\n\nKeys: {}",
+                Config::KEYS.join(", ")
+            )))
+        }
+    }
+    Ok(())
+}
+
+fn run() -> Result<()> {
+    let mut args = Args::parse(std::env::args().skip(1))?;
+    if args.command.is_empty() {
+        return Err(Error::invalid_input(USAGE));
+    }
+
+    let rest = args.command.split_off(1);
+    match args.command[0].as_str() {
+        "status" => status(&args, rest),
+        "ps" => ps(rest),
+        "trim" => trim(&args, rest),
+        "affected" => affected(&args, rest),
+        "clean" => clean(&args, rest),
+        "watch" => watch(&args, rest),
+        "metrics" => metrics(&args, rest),
+        "export" => export(rest),
+        "config" => config(&args, rest),
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        other => Err(Error::invalid_input(format!(
+            "Unknown command: {}\n\n{}",
+            other, USAGE
+        ))),
+    }
+}
+
+/// sysexits(3)-style codes so scripts can tell failures apart.
+fn exit_code(e: &Error) -> u8 {
+    match e {
+        Error::InvalidInput { .. } => 64,
+        Error::Unsupported { .. } => 69,
+        Error::OsError { .. } => 71,
+        Error::Busy { .. } => 75,
+        Error::PermissionDenied { .. } => 77,
+        Error::InvalidConfig { .. } => 78,
+        Error::Cancelled => 130,
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
@@ -0,0 +1,193 @@
+// cgroup v2 targeted reclaim via memory.reclaim
+
+use super::progress::{Control, Phase};
+use super::strategy::{Capabilities, CleaningStrategy, Outcome};
+use super::MB;
+use crate::error::{Error, Result};
+use crate::process::ProcessInfo;
+use crate::source::cgroup::{parse_flat_keyed, CgroupStat};
+use crate::{Config, MemoryInfo, MemorySource};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::{Component, Path, PathBuf};
+
+/// What a memory.reclaim write did to the cgroup's accounting.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CgroupReclaimStats {
+    pub path: PathBuf,
+    pub requested_bytes: u64,
+    /// False when the kernel gave up before reclaiming the full amount.
+    pub completed: bool,
+    pub current_before: u64,
+    pub current_after: u64,
+    /// memory.stat after minus before, for counters that changed.
+    pub stat_deltas: BTreeMap<String, i64>,
+}
+
+impl CgroupReclaimStats {
+    pub fn reclaimed_bytes(&self) -> u64 {
+        self.current_before.saturating_sub(self.current_after)
+    }
+}
+
+fn read_file(dir: &Path, name: &str) -> Result<String> {
+    let path = dir.join(name);
+    std::fs::read_to_string(&path)
+        .map_err(|e| Error::io(format!("Failed to read {}", path.display()), e))
+}
+
+fn read_current(dir: &Path) -> Result<u64> {
+    let contents = read_file(dir, "memory.current")?;
+    contents
+        .trim()
+        .parse()
+        .map_err(|_| Error::invalid_input(format!("Invalid memory.current: {}", contents.trim())))
+}
+
+/// Counters that differ between two memory.stat snapshots.
+pub fn stat_deltas(before: &str, after: &str) -> Result<BTreeMap<String, i64>> {
+    let before = parse_flat_keyed(before)?;
+    let after = parse_flat_keyed(after)?;
+
+    Ok(after
+        .iter()
+        .map(|(key, &value)| {
+            let old = before.get(key).copied().unwrap_or(0);
+            (key.clone(), value as i64 - old as i64)
+        })
+        .filter(|&(_, delta)| delta != 0)
+        .collect())
+}
+
+/// Open `<dir>/memory.reclaim` for writing. Nothing is reclaimed until it
+/// is written, so this also checks that a reclaim would be allowed.
+pub fn open_reclaim(dir: &Path) -> Result<File> {
+    let path = dir.join("memory.reclaim");
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => Error::unsupported(format!(
+                "{} not found: memory.reclaim needs Linux 5.19+ and cgroup v2",
+                path.display()
+            )),
+            ErrorKind::PermissionDenied => Error::permission_denied(format!(
+                "Permission denied writing {}: reclaim requires write access to the cgroup",
+                path.display()
+            )),
+            _ => Error::io(format!("Failed to open {}", path.display()), e),
+        })
+}
+
+/// Page cache charged to the cgroup at `dir` in bytes (memory.stat `file`),
+/// the most a reclaim can free without swapping.
+pub fn file_bytes(dir: &Path) -> Result<u64> {
+    Ok(CgroupStat::parse(&read_file(dir, "memory.stat")?)?.file)
+}
+
+/// Ask the kernel to reclaim `bytes` from the cgroup at `dir`.
+pub fn reclaim(dir: &Path, bytes: u64) -> Result<CgroupReclaimStats> {
+    let current_before = read_current(dir)?;
+    let stat_before = read_file(dir, "memory.stat")?;
+
+    let path = dir.join("memory.reclaim");
+    let mut file = open_reclaim(dir)?;
+
+    // EAGAIN means the kernel reclaimed less than asked; still report it.
+    let completed = match file.write_all(bytes.to_string().as_bytes()) {
+        Ok(()) => true,
+        Err(e) if e.kind() == ErrorKind::WouldBlock => false,
+        Err(e) => return Err(Error::io(format!("Failed to write {}", path.display()), e)),
+    };
+
+    let current_after = read_current(dir)?;
+    let stat_after = read_file(dir, "memory.stat")?;
+
+    Ok(CgroupReclaimStats {
+        path: dir.to_path_buf(),
+        requested_bytes: bytes,
+        completed,
+        current_before,
+        current_after,
+        stat_deltas: stat_deltas(&stat_before, &stat_after)?,
+    })
+}
+
+/// `path` made absolute with symlinks, `.` and `..` resolved, so two
+/// spellings of the same cgroup compare equal. Paths that do not exist are
+/// resolved lexically.
+pub fn resolve_path(path: &Path) -> PathBuf {
+    if let Ok(path) = std::fs::canonicalize(path) {
+        return path;
+    }
+    let path = match std::env::current_dir() {
+        Ok(cwd) if path.is_relative() => cwd.join(path),
+        _ => path.to_path_buf(),
+    };
+    let mut resolved = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            component => resolved.push(component),
+        }
+    }
+    resolved
+}
+
+/// Linux cgroup v2: write the target to `<path>/memory.reclaim` so only
+/// that cgroup is reclaimed from.
+pub struct CgroupReclaim {
+    pub path: PathBuf,
+}
+
+impl CleaningStrategy for CgroupReclaim {
+    fn name(&self) -> &'static str {
+        "cgroup-reclaim"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            supported: cfg!(target_os = "linux"),
+            targeted: true,
+            per_process: true,
+        }
+    }
+
+    fn requires_privilege(&self) -> bool {
+        true
+    }
+
+    /// The processes in the cgroup and below, however `path` is spelled.
+    fn affects(&self, process: &ProcessInfo) -> bool {
+        let path = resolve_path(&self.path);
+        process
+            .cgroup_dir()
+            .is_some_and(|dir| resolve_path(&dir).starts_with(&path))
+    }
+
+    fn estimate(&self, target_mb: u64, _memory: &MemoryInfo) -> Result<u64> {
+        open_reclaim(&self.path)?;
+        Ok(target_mb.min(file_bytes(&self.path)? / MB))
+    }
+
+    fn execute(
+        &self,
+        target_mb: u64,
+        _config: &Config,
+        _source: &dyn MemorySource,
+        control: &mut Control,
+    ) -> Result<Outcome> {
+        control.report(Phase::Cleaning, 0);
+        let stats = reclaim(&self.path, target_mb * MB)?;
+        Ok(Outcome {
+            processed_mb: target_mb,
+            cgroup: Some(stats),
+            ..Outcome::default()
+        })
+    }
+}
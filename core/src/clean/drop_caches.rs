@@ -0,0 +1,145 @@
+// Linux page cache cleaning via /proc/sys/vm/drop_caches
+
+use super::progress::{Control, Phase};
+use super::strategy::{Capabilities, CleaningStrategy, Outcome};
+use super::StepError;
+use crate::error::{Error, Result};
+use crate::{Config, MemoryInfo, MemorySource};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+
+pub const DROP_CACHES_PATH: &str = "/proc/sys/vm/drop_caches";
+
+/// What to drop, matching the values accepted by drop_caches.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DropCachesMode {
+    /// 1: clean page cache
+    #[default]
+    PageCache,
+    /// 2: reclaimable slab objects (dentries and inodes)
+    DentriesInodes,
+    /// 3: both
+    All,
+}
+
+impl DropCachesMode {
+    pub fn value(self) -> u8 {
+        match self {
+            DropCachesMode::PageCache => 1,
+            DropCachesMode::DentriesInodes => 2,
+            DropCachesMode::All => 3,
+        }
+    }
+
+    pub fn from_value(value: u8) -> Option<Self> {
+        match value {
+            1 => Some(DropCachesMode::PageCache),
+            2 => Some(DropCachesMode::DentriesInodes),
+            3 => Some(DropCachesMode::All),
+            _ => None,
+        }
+    }
+}
+
+/// Flush dirty pages so they can be dropped too.
+pub fn sync() -> Result<()> {
+    let status = std::process::Command::new("sync")
+        .status()
+        .map_err(|e| Error::io("Failed to run sync", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::OsError {
+            code: status.code().unwrap_or(-1),
+            message: format!("sync exited with {}", status),
+        })
+    }
+}
+
+/// Open a drop_caches file for writing. Nothing is dropped until it is
+/// written, so this also checks that a clean would be allowed.
+pub fn open(path: &Path) -> Result<File> {
+    std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(|e| match e.kind() {
+            ErrorKind::PermissionDenied => Error::permission_denied(format!(
+                "Permission denied writing {}: dropping caches requires root (CAP_SYS_ADMIN)",
+                path.display()
+            )),
+            _ => Error::io(format!("Failed to open {}", path.display()), e),
+        })
+}
+
+/// Write `mode` to a drop_caches file, optionally running `sync` first.
+pub fn drop_caches_at(path: &Path, mode: DropCachesMode, sync_first: bool) -> Result<()> {
+    if sync_first {
+        sync()?;
+    }
+
+    let mut file = open(path)?;
+    file.write_all(mode.value().to_string().as_bytes())
+        .map_err(|e| Error::io(format!("Failed to write {}", path.display()), e))
+}
+
+pub fn drop_caches(mode: DropCachesMode, sync_first: bool) -> Result<()> {
+    drop_caches_at(Path::new(DROP_CACHES_PATH), mode, sync_first)
+}
+
+/// Linux: write to /proc/sys/vm/drop_caches, optionally after `sync`.
+pub struct DropCaches {
+    pub mode: DropCachesMode,
+    pub sync: bool,
+}
+
+impl CleaningStrategy for DropCaches {
+    fn name(&self) -> &'static str {
+        "drop-caches"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            supported: cfg!(target_os = "linux"),
+            targeted: false,
+            per_process: false,
+        }
+    }
+
+    fn requires_privilege(&self) -> bool {
+        true
+    }
+
+    /// Dropping is all-or-nothing, so the target does not limit it.
+    fn estimate(&self, _target_mb: u64, memory: &MemoryInfo) -> Result<u64> {
+        open(Path::new(DROP_CACHES_PATH))?;
+        let slab_mb = memory.reclaimable_slab_mb.unwrap_or(0);
+        Ok(match self.mode {
+            DropCachesMode::PageCache => memory.page_cache_mb,
+            DropCachesMode::DentriesInodes => slab_mb,
+            DropCachesMode::All => memory.page_cache_mb + slab_mb,
+        })
+    }
+
+    fn execute(
+        &self,
+        _target_mb: u64,
+        _config: &Config,
+        _source: &dyn MemorySource,
+        control: &mut Control,
+    ) -> Result<Outcome> {
+        let mut outcome = Outcome::default();
+        if self.sync {
+            control.report(Phase::Syncing, 0);
+            if let Err(e) = sync() {
+                outcome.errors.push(StepError::new("sync", e));
+            }
+            control.check()?;
+        }
+        control.report(Phase::Cleaning, 0);
+        drop_caches(self.mode, false)?;
+        Ok(outcome)
+    }
+}
@@ -0,0 +1,460 @@
+// Memory cleaning strategies
+
+use crate::error::{Error, Result};
+use crate::process::{ProcessDecision, ProcessInfo, ProcessRules, ProcessSource};
+use crate::{Config, MemoryInfo, MemorySource};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Instant;
+
+pub mod cgroup_reclaim;
+pub mod drop_caches;
+pub mod pipeline;
+pub mod plan;
+pub mod pressure;
+pub mod progress;
+pub mod strategy;
+#[cfg(target_os = "windows")]
+pub mod win32;
+pub mod working_set;
+
+pub use cgroup_reclaim::CgroupReclaimStats;
+pub use drop_caches::DropCachesMode;
+pub use pipeline::{PipelineStep, StepReport, StopCondition};
+pub use plan::{plan_clean, CleanPlan};
+pub use pressure::{PressureGuard, StopReason};
+pub use progress::{CancelToken, Control, Phase, Progress};
+pub use strategy::{Capabilities, CleaningStrategy, Outcome};
+
+use working_set::working_set_mb;
+
+const MB: u64 = 1024 * 1024;
+
+/// How to free memory. Each variant is built into a `CleaningStrategy`
+/// by `Strategy::build`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Strategy {
+    /// Windows: commit and release memory in chunks to push the standby
+    /// list out. `guard` stops it short of starving the system.
+    AllocationPressure {
+        #[serde(default)]
+        guard: PressureGuard,
+    },
+    /// Windows: trim our own working set.
+    TrimWorkingSet,
+    /// Linux: write to /proc/sys/vm/drop_caches, optionally after `sync`.
+    DropCaches { mode: DropCachesMode, sync: bool },
+    /// Linux cgroup v2: write the target to `<path>/memory.reclaim` so only
+    /// that cgroup is reclaimed from.
+    CgroupReclaim { path: PathBuf },
+    /// Several strategies in order, each with a condition to stop after it.
+    Pipeline { steps: Vec<PipelineStep> },
+}
+
+/// Change in each category between two snapshots; positive means it grew.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryDelta {
+    pub cache_mb: i64,
+    pub available_mb: i64,
+    pub used_mb: i64,
+    /// This process's working set (RSS); `None` where it cannot be read
+    pub working_set_mb: Option<i64>,
+}
+
+/// A step that failed without aborting the clean, such as `sync` before
+/// dropping caches or trimming our working set.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StepError {
+    pub step: String,
+    pub error: Error,
+}
+
+impl StepError {
+    pub fn new(step: impl Into<String>, error: Error) -> Self {
+        Self {
+            step: step.into(),
+            error,
+        }
+    }
+}
+
+/// What a clean did, measured from snapshots taken around it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CleanReport {
+    pub strategy: Strategy,
+    pub target_mb: u64,
+    pub before: MemoryInfo,
+    pub after: MemoryInfo,
+    pub delta: MemoryDelta,
+    /// Cache freed: the drop in memory.current for `CgroupReclaim`, the drop
+    /// in `cache_mb` otherwise
+    pub cleaned_mb: u64,
+    /// MB the strategy pushed through (allocated, or asked the kernel to
+    /// reclaim); not the same as what was freed
+    pub processed_mb: u64,
+    pub duration_ms: u64,
+    /// memory.current and memory.stat changes for `CgroupReclaim`
+    pub cgroup: Option<CgroupReclaimStats>,
+    /// Why `AllocationPressure` stopped short of or at the target
+    pub stop_reason: Option<StopReason>,
+    pub errors: Vec<StepError>,
+    /// What each step of a `Pipeline` did
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepReport>,
+}
+
+fn delta(before: u64, after: u64) -> i64 {
+    after as i64 - before as i64
+}
+
+impl Default for Strategy {
+    fn default() -> Self {
+        if cfg!(target_os = "linux") {
+            Strategy::DropCaches {
+                mode: DropCachesMode::PageCache,
+                sync: true,
+            }
+        } else {
+            Strategy::pressure_then_trim(PressureGuard::default())
+        }
+    }
+}
+
+impl Strategy {
+    /// Parse a strategy spec:
+    /// `alloc-pressure[:floor=<available MB>][:max=<percent of RAM>]`,
+    /// `trim-working-set`, `drop-caches[:<1|2|3>][:no-sync]` or
+    /// `cgroup-reclaim:<cgroup dir>`. A pipeline is a comma-separated list
+    /// of these, each optionally followed by `@<stop condition>`, e.g.
+    /// `drop-caches:1@stop-threshold,drop-caches:3`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        if !spec.contains([',', '@']) {
+            return Self::parse_step(spec);
+        }
+        let steps = spec
+            .split(',')
+            .map(|step| {
+                let (strategy, stop) = match step.rsplit_once('@') {
+                    Some((strategy, stop)) => (strategy, StopCondition::parse(stop)?),
+                    None => (step, StopCondition::Never),
+                };
+                Ok(PipelineStep {
+                    strategy: Self::parse_step(strategy)?,
+                    stop,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Strategy::Pipeline { steps })
+    }
+
+    fn parse_step(spec: &str) -> Result<Self> {
+        let invalid = || Error::invalid_input(format!("Unknown cleaning strategy: {}", spec));
+        if let Some(path) = spec.strip_prefix("cgroup-reclaim:") {
+            return Ok(Strategy::CgroupReclaim { path: path.into() });
+        }
+
+        let mut parts = spec.split(':');
+        match parts.next() {
+            Some("alloc-pressure") => {
+                let mut guard = PressureGuard::default();
+                for part in parts {
+                    if let Some(value) = part.strip_prefix("floor=") {
+                        guard.floor_mb = value.parse().map_err(|_| invalid())?;
+                    } else if let Some(value) = part.strip_prefix("max=") {
+                        guard.max_commit_percent = value.parse().map_err(|_| invalid())?;
+                    } else {
+                        return Err(invalid());
+                    }
+                }
+                Ok(Strategy::AllocationPressure { guard })
+            }
+            Some("trim-working-set") if parts.next().is_none() => Ok(Strategy::TrimWorkingSet),
+            Some("drop-caches") => {
+                let mut mode = DropCachesMode::default();
+                let mut sync = true;
+                for part in parts {
+                    match part {
+                        "no-sync" => sync = false,
+                        value => {
+                            mode = value
+                                .parse()
+                                .ok()
+                                .and_then(DropCachesMode::from_value)
+                                .ok_or_else(invalid)?
+                        }
+                    }
+                }
+                Ok(Strategy::DropCaches { mode, sync })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    /// Formats as the spec accepted by `Strategy::parse`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Strategy::AllocationPressure { guard } => write!(
+                f,
+                "alloc-pressure:floor={}:max={}",
+                guard.floor_mb, guard.max_commit_percent
+            ),
+            Strategy::DropCaches { mode, sync } => {
+                write!(f, "drop-caches:{}", mode.value())?;
+                if !sync {
+                    write!(f, ":no-sync")?;
+                }
+                Ok(())
+            }
+            Strategy::TrimWorkingSet => write!(f, "trim-working-set"),
+            Strategy::CgroupReclaim { path } => write!(f, "cgroup-reclaim:{}", path.display()),
+            Strategy::Pipeline { steps } => {
+                for (i, step) in steps.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", step.strategy)?;
+                    if step.stop != StopCondition::Never {
+                        write!(f, "@{}", step.stop)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Strategy {
+    /// The Windows default: allocation pressure, then trimming our own
+    /// working set of the pages it pulled in.
+    pub fn pressure_then_trim(guard: PressureGuard) -> Self {
+        Strategy::Pipeline {
+            steps: vec![
+                PipelineStep {
+                    strategy: Strategy::AllocationPressure { guard },
+                    stop: StopCondition::Never,
+                },
+                PipelineStep {
+                    strategy: Strategy::TrimWorkingSet,
+                    stop: StopCondition::Never,
+                },
+            ],
+        }
+    }
+
+    /// The implementation that runs this strategy.
+    pub fn build(&self) -> Box<dyn CleaningStrategy> {
+        match self {
+            Strategy::AllocationPressure { guard } => {
+                Box::new(pressure::AllocationPressure { guard: *guard })
+            }
+            Strategy::TrimWorkingSet => Box::new(working_set::TrimWorkingSet),
+            Strategy::DropCaches { mode, sync } => Box::new(drop_caches::DropCaches {
+                mode: *mode,
+                sync: *sync,
+            }),
+            Strategy::CgroupReclaim { path } => {
+                Box::new(cgroup_reclaim::CgroupReclaim { path: path.clone() })
+            }
+            Strategy::Pipeline { steps } => Box::new(pipeline::Pipeline::new(steps)),
+        }
+    }
+
+    /// Whether this strategy acts on `process` directly. Dropping caches and
+    /// allocation pressure work on system-wide caches rather than on any
+    /// process, so only cgroup reclaim does, on the processes in its cgroup
+    /// and below.
+    pub fn affects(&self, process: &ProcessInfo) -> bool {
+        self.build().affects(process)
+    }
+}
+
+/// The processes a clean with `strategy` would act on, each marked with
+/// whether `rules` exclude it.
+pub fn affected_processes(
+    strategy: &Strategy,
+    rules: &ProcessRules,
+    processes: &dyn ProcessSource,
+) -> Result<Vec<ProcessDecision>> {
+    let cleaner = strategy.build();
+    if !cleaner.capabilities().per_process {
+        return Ok(Vec::new());
+    }
+    let affected = processes
+        .list()?
+        .into_iter()
+        .filter(|p| cleaner.affects(p))
+        .collect();
+    Ok(rules.decide(affected))
+}
+
+/// Refuse a clean that would act on a process `rules` exclude.
+pub fn check_process_rules(
+    strategy: &Strategy,
+    rules: &ProcessRules,
+    processes: &dyn ProcessSource,
+) -> Result<()> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+    let affected = affected_processes(strategy, rules, processes)?;
+    match excluded_error(strategy, &affected) {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// The error for a clean whose `affected` processes include excluded ones.
+fn excluded_error(strategy: &Strategy, affected: &[ProcessDecision]) -> Option<Error> {
+    let excluded: Vec<String> = affected
+        .iter()
+        .filter_map(|d| {
+            let reason = d.excluded.as_ref()?;
+            Some(format!(
+                "{} ({}, {})",
+                d.process.name, d.process.pid, reason
+            ))
+        })
+        .collect();
+    if excluded.is_empty() {
+        return None;
+    }
+    Some(Error::invalid_config(format!(
+        "{} would act on excluded processes: {}",
+        strategy,
+        excluded.join(", ")
+    )))
+}
+
+/// Clean memory with `strategy`, reading `source` before and after.
+///
+/// Allocation pressure cycles up to `target_mb` through memory, stopping
+/// early at the limits in its `PressureGuard`. Dropping
+/// caches is all-or-nothing, so `target_mb` is ignored. Cgroup reclaim asks
+/// the kernel for `target_mb`. A pipeline runs its steps until one's stop
+/// condition holds, checking thresholds from `config`. A failure of the
+/// strategy itself is returned as an error; failures of side steps are
+/// collected in `errors`.
+pub fn clean_memory_cache(
+    strategy: &Strategy,
+    target_mb: u64,
+    config: &Config,
+    source: &dyn MemorySource,
+) -> Result<CleanReport> {
+    clean_with_progress(
+        strategy,
+        target_mb,
+        config,
+        source,
+        &CancelToken::new(),
+        &mut |_| {},
+    )
+}
+
+/// `clean_memory_cache` that reports each step to `on_progress` and stops
+/// with `Error::Cancelled` at the next step or chunk once `cancel` is set.
+pub fn clean_with_progress(
+    strategy: &Strategy,
+    target_mb: u64,
+    config: &Config,
+    source: &dyn MemorySource,
+    cancel: &CancelToken,
+    on_progress: &mut dyn FnMut(&Progress),
+) -> Result<CleanReport> {
+    let mut control = Control::new(cancel, on_progress, target_mb);
+    let started = Instant::now();
+    control.report(Phase::Measuring, 0);
+    let before = source.read()?;
+    let working_set_before = working_set_mb();
+    control.check()?;
+
+    let mut outcome = strategy
+        .build()
+        .execute(target_mb, config, source, &mut control)?;
+
+    control.report(Phase::Measuring, outcome.processed_mb);
+    let after = match source.read() {
+        Ok(after) => after,
+        Err(e) => {
+            outcome.errors.push(StepError::new("read memory after", e));
+            before.clone()
+        }
+    };
+    let working_set_after = working_set_mb();
+
+    // A pipeline's cgroup stats only cover its reclaim step
+    let cleaned_mb = match (&outcome.cgroup, strategy) {
+        (Some(stats), Strategy::CgroupReclaim { .. }) => stats.reclaimed_bytes() / MB,
+        _ => before.cache_mb.saturating_sub(after.cache_mb),
+    };
+    let delta = MemoryDelta {
+        cache_mb: delta(before.cache_mb, after.cache_mb),
+        available_mb: delta(before.available_mb, after.available_mb),
+        used_mb: delta(before.used_mb, after.used_mb),
+        working_set_mb: working_set_before
+            .zip(working_set_after)
+            .map(|(before, after)| delta(before, after)),
+    };
+
+    control.report(Phase::Finished, outcome.processed_mb);
+    Ok(CleanReport {
+        strategy: strategy.clone(),
+        target_mb,
+        before,
+        after,
+        delta,
+        cleaned_mb,
+        processed_mb: outcome.processed_mb,
+        duration_ms: started.elapsed().as_millis() as u64,
+        cgroup: outcome.cgroup,
+        stop_reason: outcome.stop_reason,
+        errors: outcome.errors,
+        steps: outcome.steps,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tests::process;
+    use crate::process::ProcessRule;
+
+    fn deny(pattern: &str) -> ProcessRules {
+        ProcessRules {
+            allow: Vec::new(),
+            deny: vec![ProcessRule::Name {
+                pattern: pattern.to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn denied_process_blocks_cgroup_reclaim_however_the_path_is_spelled() {
+        let mut db = process(10, "postgres");
+        db.cgroup = Some("/mcm-test.slice/postgresql.service".to_string());
+        let processes = vec![db];
+
+        for path in [
+            "/sys/fs/cgroup/mcm-test.slice",
+            "/sys/fs/cgroup/./mcm-test.slice/",
+            "/sys/fs/cgroup//mcm-test.slice",
+            "/sys/fs/cgroup/mcm-other.slice/../mcm-test.slice",
+            "/sys/fs/cgroup/mcm-test.slice/postgresql.service/..",
+        ] {
+            let strategy = Strategy::parse(&format!("cgroup-reclaim:{}", path)).unwrap();
+            let err = check_process_rules(&strategy, &deny("postgres"), &processes).unwrap_err();
+            assert!(
+                matches!(err, Error::InvalidConfig { .. }),
+                "{}: {:?}",
+                path,
+                err
+            );
+        }
+
+        let elsewhere = Strategy::parse("cgroup-reclaim:/sys/fs/cgroup/mcm-other.slice").unwrap();
+        assert!(check_process_rules(&elsewhere, &deny("postgres"), &processes).is_ok());
+    }
+}
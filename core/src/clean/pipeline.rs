@@ -0,0 +1,416 @@
+// Pipelines: several strategies run in order, with stop conditions
+
+use super::progress::Control;
+use super::strategy::{Capabilities, CleaningStrategy, Outcome};
+use super::{StepError, Strategy};
+use crate::error::{Error, Result};
+use crate::process::ProcessInfo;
+use crate::{Config, MemoryInfo, MemorySource};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// When to end a pipeline after a step.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum StopCondition {
+    /// Always go on to the next step
+    #[default]
+    Never,
+    /// Stop once the pipeline has freed `target_mb` of cache
+    TargetFreed,
+    /// Stop once memory is back at the configured stop threshold
+    StopThreshold,
+}
+
+impl StopCondition {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "never" => Ok(StopCondition::Never),
+            "target" => Ok(StopCondition::TargetFreed),
+            "stop-threshold" => Ok(StopCondition::StopThreshold),
+            _ => Err(Error::invalid_input(format!(
+                "Unknown stop condition: {} (expected never, target or stop-threshold)",
+                value
+            ))),
+        }
+    }
+
+    fn holds(self, config: &Config, memory: &MemoryInfo, freed_mb: u64, target_mb: u64) -> bool {
+        match self {
+            StopCondition::Never => false,
+            StopCondition::TargetFreed => freed_mb >= target_mb,
+            StopCondition::StopThreshold => memory.evaluate(config).should_stop,
+        }
+    }
+}
+
+impl fmt::Display for StopCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            StopCondition::Never => "never",
+            StopCondition::TargetFreed => "target",
+            StopCondition::StopThreshold => "stop-threshold",
+        })
+    }
+}
+
+/// A pipeline step: a strategy and when to stop after it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct PipelineStep {
+    pub strategy: Strategy,
+    #[serde(default)]
+    pub stop: StopCondition,
+}
+
+/// What one pipeline step did.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct StepReport {
+    pub strategy: Strategy,
+    pub processed_mb: u64,
+    /// Drop in `cache_mb` across the step
+    pub cleaned_mb: u64,
+    pub error: Option<Error>,
+    /// The step's stop condition held, so the pipeline ended after it
+    pub stopped: bool,
+}
+
+/// Runs its steps in order. A failing step is recorded and the next one
+/// runs; the pipeline only fails if every step that ran failed.
+pub struct Pipeline {
+    steps: Vec<(Strategy, Box<dyn CleaningStrategy>, StopCondition)>,
+}
+
+impl Pipeline {
+    pub fn new(steps: &[PipelineStep]) -> Self {
+        Self {
+            steps: steps
+                .iter()
+                .map(|step| (step.strategy.clone(), step.strategy.build(), step.stop))
+                .collect(),
+        }
+    }
+}
+
+impl CleaningStrategy for Pipeline {
+    fn name(&self) -> &'static str {
+        "pipeline"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.steps
+            .iter()
+            .map(|(_, step, _)| step.capabilities())
+            .fold(Capabilities::default(), |all, step| Capabilities {
+                supported: all.supported || step.supported,
+                targeted: all.targeted || step.targeted,
+                per_process: all.per_process || step.per_process,
+            })
+    }
+
+    fn requires_privilege(&self) -> bool {
+        self.steps
+            .iter()
+            .any(|(_, step, _)| step.requires_privilege())
+    }
+
+    fn affects(&self, process: &ProcessInfo) -> bool {
+        self.steps.iter().any(|(_, step, _)| step.affects(process))
+    }
+
+    /// The largest step estimate, since steps mostly free the same cache.
+    /// Fails only if no step could run.
+    fn estimate(&self, target_mb: u64, memory: &MemoryInfo) -> Result<u64> {
+        let mut first_error = None;
+        let mut estimate = None;
+        for (_, step, _) in &self.steps {
+            match step.estimate(target_mb, memory) {
+                Ok(mb) => estimate = Some(estimate.unwrap_or(0).max(mb)),
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        match (estimate, first_error) {
+            (Some(mb), _) => Ok(mb),
+            (None, Some(e)) => Err(e),
+            (None, None) => Err(Error::invalid_config("Cleaning pipeline has no steps")),
+        }
+    }
+
+    fn execute(
+        &self,
+        target_mb: u64,
+        config: &Config,
+        source: &dyn MemorySource,
+        control: &mut Control,
+    ) -> Result<Outcome> {
+        let before = source.read()?;
+        let mut last = before.clone();
+        let mut outcome = Outcome::default();
+        let mut first_error = None;
+        let mut succeeded = false;
+
+        for (strategy, step, stop) in &self.steps {
+            control.check()?;
+            // Later steps only go after what is left of the target
+            let freed_mb = before.cache_mb.saturating_sub(last.cache_mb);
+            let (processed_mb, error) =
+                match step.execute(target_mb.saturating_sub(freed_mb), config, source, control) {
+                    Ok(step_outcome) => {
+                        succeeded = true;
+                        outcome.processed_mb += step_outcome.processed_mb;
+                        outcome.cgroup = step_outcome.cgroup.or(outcome.cgroup);
+                        outcome.stop_reason = step_outcome.stop_reason.or(outcome.stop_reason);
+                        outcome.errors.extend(step_outcome.errors);
+                        (step_outcome.processed_mb, None)
+                    }
+                    Err(Error::Cancelled) => return Err(Error::Cancelled),
+                    Err(e) => {
+                        outcome
+                            .errors
+                            .push(StepError::new(strategy.to_string(), e.clone()));
+                        first_error.get_or_insert(e.clone());
+                        (0, Some(e))
+                    }
+                };
+
+            // Without a reading the stop condition cannot be checked, so the
+            // step is reported as having freed nothing and the next one runs
+            let now = match source.read() {
+                Ok(now) => now,
+                Err(e) => {
+                    outcome
+                        .errors
+                        .push(StepError::new(format!("read memory after {}", strategy), e));
+                    outcome.steps.push(StepReport {
+                        strategy: strategy.clone(),
+                        processed_mb,
+                        cleaned_mb: 0,
+                        error,
+                        stopped: false,
+                    });
+                    continue;
+                }
+            };
+            let freed_mb = before.cache_mb.saturating_sub(now.cache_mb);
+            let stopped = stop.holds(config, &now, freed_mb, target_mb);
+            outcome.steps.push(StepReport {
+                strategy: strategy.clone(),
+                processed_mb,
+                cleaned_mb: last.cache_mb.saturating_sub(now.cache_mb),
+                error,
+                stopped,
+            });
+            last = now;
+            if stopped {
+                break;
+            }
+        }
+
+        match first_error {
+            Some(e) if !succeeded => Err(e),
+            None if self.steps.is_empty() => {
+                Err(Error::invalid_config("Cleaning pipeline has no steps"))
+            }
+            _ => Ok(outcome),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::CancelToken;
+    use crate::memory::tests::memory;
+    use crate::source::ScriptedSource;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    /// A step that returns `result` and counts its runs.
+    struct Fake {
+        result: Result<u64>,
+        runs: Rc<Cell<u32>>,
+    }
+
+    impl CleaningStrategy for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn capabilities(&self) -> Capabilities {
+            Capabilities::default()
+        }
+
+        fn requires_privilege(&self) -> bool {
+            false
+        }
+
+        fn estimate(&self, target_mb: u64, _memory: &MemoryInfo) -> Result<u64> {
+            self.result.clone().map(|mb| mb.min(target_mb))
+        }
+
+        fn execute(
+            &self,
+            _target_mb: u64,
+            _config: &Config,
+            _source: &dyn MemorySource,
+            _control: &mut Control,
+        ) -> Result<Outcome> {
+            self.runs.set(self.runs.get() + 1);
+            self.result.clone().map(|processed_mb| Outcome {
+                processed_mb,
+                ..Outcome::default()
+            })
+        }
+    }
+
+    /// A pipeline of fakes, each labelled as a drop-caches step of its
+    /// mode, and the run counter of each.
+    fn fakes(steps: Vec<(Result<u64>, StopCondition)>) -> (Pipeline, Vec<Rc<Cell<u32>>>) {
+        let mut runs = Vec::new();
+        let steps = steps
+            .into_iter()
+            .enumerate()
+            .map(|(i, (result, stop))| {
+                let counter = Rc::new(Cell::new(0));
+                runs.push(Rc::clone(&counter));
+                let strategy = Strategy::parse(&format!("drop-caches:{}", i % 3 + 1)).unwrap();
+                let step: Box<dyn CleaningStrategy> = Box::new(Fake {
+                    result,
+                    runs: counter,
+                });
+                (strategy, step, stop)
+            })
+            .collect();
+        (Pipeline { steps }, runs)
+    }
+
+    fn execute(pipeline: &Pipeline, target_mb: u64, source: &ScriptedSource) -> Result<Outcome> {
+        let config = Config {
+            start_threshold_mb: 1000,
+            stop_threshold_mb: 500,
+            ..Config::default()
+        };
+        let cancel = CancelToken::new();
+        let mut on_progress = |_: &_| {};
+        let mut control = Control::new(&cancel, &mut on_progress, target_mb);
+        pipeline.execute(target_mb, &config, source, &mut control)
+    }
+
+    fn failed() -> Error {
+        Error::permission_denied("no access")
+    }
+
+    #[test]
+    fn parses_and_formats_stop_conditions() {
+        for (value, stop) in [
+            ("never", StopCondition::Never),
+            ("target", StopCondition::TargetFreed),
+            ("stop-threshold", StopCondition::StopThreshold),
+        ] {
+            assert_eq!(StopCondition::parse(value).unwrap(), stop);
+            assert_eq!(stop.to_string(), value);
+        }
+        assert!(StopCondition::parse("later").is_err());
+
+        let spec = "drop-caches:1@stop-threshold,drop-caches:3@target,drop-caches:2";
+        assert_eq!(Strategy::parse(spec).unwrap().to_string(), spec);
+    }
+
+    #[test]
+    fn target_condition_ends_the_pipeline_once_enough_is_freed() {
+        let (pipeline, runs) = fakes(vec![
+            (Ok(100), StopCondition::TargetFreed),
+            (Ok(200), StopCondition::TargetFreed),
+            (Ok(300), StopCondition::Never),
+        ]);
+        let source = ScriptedSource::new(vec![
+            memory(8000, 4000, 2000),
+            memory(8000, 4100, 1900),
+            memory(8000, 4300, 1700),
+        ]);
+        let outcome = execute(&pipeline, 300, &source).unwrap();
+        assert_eq!(outcome.steps.len(), 2);
+        assert_eq!(outcome.steps[0].cleaned_mb, 100);
+        assert!(!outcome.steps[0].stopped);
+        assert_eq!(outcome.steps[1].cleaned_mb, 200);
+        assert!(outcome.steps[1].stopped);
+        assert_eq!(outcome.processed_mb, 300);
+        assert_eq!(runs[2].get(), 0);
+    }
+
+    #[test]
+    fn stop_threshold_condition_ends_the_pipeline_at_the_stop_threshold() {
+        let (pipeline, runs) = fakes(vec![
+            (Ok(400), StopCondition::StopThreshold),
+            (Ok(400), StopCondition::StopThreshold),
+            (Ok(400), StopCondition::Never),
+        ]);
+        let source = ScriptedSource::new(vec![
+            memory(8000, 4000, 1200),
+            memory(8000, 4400, 800),
+            memory(8000, 4800, 450),
+        ]);
+        let outcome = execute(&pipeline, 700, &source).unwrap();
+        assert_eq!(outcome.steps.len(), 2);
+        assert!(outcome.steps[1].stopped);
+        assert_eq!(runs[2].get(), 0);
+    }
+
+    #[test]
+    fn failing_step_is_reported_and_the_next_one_runs() {
+        let (pipeline, runs) = fakes(vec![
+            (Err(failed()), StopCondition::Never),
+            (Ok(300), StopCondition::Never),
+        ]);
+        let source = ScriptedSource::new(vec![
+            memory(8000, 4000, 2000),
+            memory(8000, 4000, 2000),
+            memory(8000, 4300, 1700),
+        ]);
+        let outcome = execute(&pipeline, 300, &source).unwrap();
+        assert_eq!(runs[1].get(), 1);
+        assert_eq!(outcome.steps[0].error, Some(failed()));
+        assert_eq!(outcome.steps[1].error, None);
+        assert_eq!(outcome.steps[1].cleaned_mb, 300);
+        assert_eq!(outcome.errors.len(), 1);
+        assert_eq!(outcome.errors[0].step, "drop-caches:1");
+    }
+
+    #[test]
+    fn fails_only_if_every_step_fails() {
+        let (pipeline, _) = fakes(vec![
+            (Err(failed()), StopCondition::Never),
+            (Err(Error::unsupported("nope")), StopCondition::Never),
+        ]);
+        let source = ScriptedSource::new(vec![memory(8000, 4000, 2000)]);
+        assert_eq!(execute(&pipeline, 300, &source).unwrap_err(), failed());
+
+        let (empty, _) = fakes(Vec::new());
+        assert!(matches!(
+            execute(&empty, 300, &source),
+            Err(Error::InvalidConfig { .. })
+        ));
+    }
+
+    #[test]
+    fn failed_reading_keeps_earlier_steps_and_skips_the_stop_condition() {
+        let (pipeline, runs) = fakes(vec![
+            (Ok(100), StopCondition::TargetFreed),
+            (Ok(200), StopCondition::Never),
+        ]);
+        let source = ScriptedSource::with_results(vec![
+            Ok(memory(8000, 4000, 2000)),
+            Err(Error::invalid_input("meminfo went away")),
+            Ok(memory(8000, 4300, 1700)),
+        ]);
+        let outcome = execute(&pipeline, 100, &source).unwrap();
+        assert_eq!(runs[1].get(), 1);
+        assert_eq!(outcome.steps.len(), 2);
+        assert_eq!(outcome.steps[0].processed_mb, 100);
+        assert!(!outcome.steps[0].stopped);
+        assert_eq!(outcome.steps[1].cleaned_mb, 300);
+        assert_eq!(outcome.errors.len(), 1);
+        assert_eq!(outcome.errors[0].step, "read memory after drop-caches:1");
+    }
+}
@@ -0,0 +1,239 @@
+// Dry-run plans: what a clean would do, worked out without touching memory
+
+use super::{affected_processes, excluded_error, Capabilities, Strategy};
+use crate::error::{Error, Result};
+use crate::process::{ProcessDecision, ProcessSource};
+use crate::threshold::Evaluation;
+use crate::{Config, MemoryInfo, MemorySource};
+use serde::{Deserialize, Serialize};
+
+/// What a clean with `strategy` and `target_mb` would do right now.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CleanPlan {
+    pub strategy: Strategy,
+    pub target_mb: u64,
+    pub capabilities: Capabilities,
+    /// Needs root or write access to a system file
+    pub requires_privilege: bool,
+    pub memory: MemoryInfo,
+    /// Where `memory` sits against the configured thresholds; automatic
+    /// cleaning would start now if `should_start` is set
+    pub evaluation: Evaluation,
+    /// Rough MB the clean would free, from what the strategy can reach
+    pub estimated_mb: u64,
+    /// Processes the strategy acts on directly, each marked if the process
+    /// rules exclude it
+    pub affected: Vec<ProcessDecision>,
+    /// Why the clean would be refused or fail before freeing anything:
+    /// excluded processes, a missing permission or an unsupported platform
+    pub blockers: Vec<Error>,
+}
+
+impl CleanPlan {
+    /// Whether the clean would go ahead.
+    pub fn would_run(&self) -> bool {
+        self.blockers.is_empty()
+    }
+}
+
+/// Work out a clean without running it: read memory, list the processes
+/// `strategy` affects, estimate what it would free and check the same
+/// permissions and process rules the clean would. Only errors reading
+/// memory are returned; everything else ends up in `blockers`.
+pub fn plan_clean(
+    strategy: &Strategy,
+    target_mb: u64,
+    config: &Config,
+    source: &dyn MemorySource,
+    processes: &dyn ProcessSource,
+) -> Result<CleanPlan> {
+    let memory = source.read()?;
+    let cleaner = strategy.build();
+    let capabilities = cleaner.capabilities();
+    let mut blockers = Vec::new();
+
+    let affected = match affected_processes(strategy, &config.process_rules, processes) {
+        Ok(affected) => affected,
+        Err(e) => {
+            blockers.push(e);
+            Vec::new()
+        }
+    };
+    blockers.extend(excluded_error(strategy, &affected));
+
+    let estimated_mb = if !capabilities.supported {
+        blockers.push(Error::unsupported(format!(
+            "{} is not supported on this platform",
+            cleaner.name()
+        )));
+        0
+    } else {
+        match cleaner.estimate(target_mb, &memory) {
+            Ok(estimated_mb) => estimated_mb,
+            Err(e) => {
+                blockers.push(e);
+                0
+            }
+        }
+    };
+
+    Ok(CleanPlan {
+        strategy: strategy.clone(),
+        target_mb,
+        capabilities,
+        requires_privilege: cleaner.requires_privilege(),
+        evaluation: memory.evaluate(config),
+        memory,
+        estimated_mb,
+        affected,
+        blockers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::tests::memory;
+    use crate::process::tests::process;
+    use crate::process::{ProcessInfo, ProcessRule, ProcessRules};
+    use crate::source::ScriptedSource;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A directory with the cgroup files reclaim reads, charged `file_mb`
+    /// of page cache; memory.reclaim is a plain file, so nothing is
+    /// reclaimed.
+    struct FakeCgroup(PathBuf);
+
+    impl FakeCgroup {
+        fn new(name: &str, file_mb: u64) -> Self {
+            let dir = std::env::temp_dir().join(format!("mcm-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join("memory.reclaim"), "").unwrap();
+            fs::write(
+                dir.join("memory.stat"),
+                format!("anon 0\nfile {}\n", file_mb * 1024 * 1024),
+            )
+            .unwrap();
+            Self(dir)
+        }
+
+        fn strategy(&self) -> Strategy {
+            Strategy::CgroupReclaim {
+                path: self.0.clone(),
+            }
+        }
+    }
+
+    impl Drop for FakeCgroup {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn plan(strategy: &Strategy, target_mb: u64, config: &Config) -> CleanPlan {
+        plan_with(strategy, target_mb, config, Vec::new())
+    }
+
+    fn plan_with(
+        strategy: &Strategy,
+        target_mb: u64,
+        config: &Config,
+        processes: Vec<ProcessInfo>,
+    ) -> CleanPlan {
+        let source = ScriptedSource::new(vec![memory(8192, 4096, 3000)]);
+        plan_clean(strategy, target_mb, config, &source, &processes).unwrap()
+    }
+
+    #[test]
+    fn estimate_is_capped_by_the_target() {
+        let cgroup = FakeCgroup::new("plan-capped", 2048);
+        let config = Config::default();
+
+        let small = plan(&cgroup.strategy(), 512, &config);
+        assert!(small.would_run(), "{:?}", small.blockers);
+        assert_eq!(small.estimated_mb, 512);
+        assert!(small.requires_privilege);
+
+        // Only what the cgroup holds can be freed
+        let large = plan(&cgroup.strategy(), 4096, &config);
+        assert_eq!(large.estimated_mb, 2048);
+    }
+
+    #[test]
+    fn plan_evaluates_the_current_reading() {
+        let cgroup = FakeCgroup::new("plan-evaluation", 2048);
+        let plan = plan(&cgroup.strategy(), 1024, &Config::default());
+        assert_eq!(plan.memory, memory(8192, 4096, 3000));
+        assert!(plan.evaluation.should_start);
+        assert_eq!(plan.evaluation.target_mb, 1976);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn unsupported_strategy_is_a_blocker() {
+        let strategy = Strategy::parse("alloc-pressure").unwrap();
+        let plan = plan(&strategy, 1024, &Config::default());
+        assert!(!plan.capabilities.supported);
+        assert_eq!(plan.estimated_mb, 0);
+        assert!(
+            matches!(plan.blockers[..], [Error::Unsupported { .. }]),
+            "{:?}",
+            plan.blockers
+        );
+    }
+
+    #[test]
+    fn missing_cgroup_is_a_blocker() {
+        let strategy = Strategy::parse("cgroup-reclaim:/sys/fs/cgroup/mcm-missing.slice").unwrap();
+        let plan = plan(&strategy, 1024, &Config::default());
+        assert_eq!(plan.estimated_mb, 0);
+        assert_eq!(plan.blockers.len(), 1, "{:?}", plan.blockers);
+        assert!(!plan.would_run());
+    }
+
+    #[test]
+    fn excluded_process_is_a_blocker() {
+        let config = Config {
+            process_rules: ProcessRules {
+                allow: Vec::new(),
+                deny: vec![ProcessRule::Name {
+                    pattern: "postgres".to_string(),
+                }],
+            },
+            ..Config::default()
+        };
+        let mut db = process(10, "postgres");
+        db.cgroup = Some("/mcm-plan.slice/postgresql.service".to_string());
+        let mut web = process(11, "nginx");
+        web.cgroup = Some("/mcm-plan.slice/nginx.service".to_string());
+        let mut other = process(12, "postgres");
+        other.cgroup = Some("/mcm-other.slice".to_string());
+
+        let strategy = Strategy::parse("cgroup-reclaim:/sys/fs/cgroup/mcm-plan.slice").unwrap();
+        let plan = plan_with(&strategy, 1024, &config, vec![db, web, other]);
+
+        let affected: Vec<(u32, bool)> = plan
+            .affected
+            .iter()
+            .map(|d| (d.process.pid, d.excluded.is_some()))
+            .collect();
+        assert_eq!(affected, [(10, true), (11, false)]);
+        match &plan.blockers[0] {
+            Error::InvalidConfig { message, .. } => {
+                assert!(message.contains("postgres (10"), "{}", message)
+            }
+            e => panic!("unexpected blocker {:?}", e),
+        }
+    }
+
+    #[test]
+    fn failed_reading_is_returned() {
+        let source = ScriptedSource::with_results(vec![Err(Error::unsupported("no memory"))]);
+        let strategy = Strategy::parse("drop-caches").unwrap();
+        let processes: Vec<ProcessInfo> = Vec::new();
+        let err = plan_clean(&strategy, 1024, &Config::default(), &source, &processes);
+        assert_eq!(err.unwrap_err(), Error::unsupported("no memory"));
+    }
+}
@@ -0,0 +1,324 @@
+// Allocation-pressure cleaning and its safety limits
+
+use super::progress::{Control, Phase};
+use super::strategy::{Capabilities, CleaningStrategy, Outcome};
+use crate::error::{Error, Result};
+use crate::{Config, MemoryInfo, MemorySource};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::Duration;
+
+/// Size of each commit-and-release cycle.
+pub const CHUNK_MB: u64 = 100;
+
+/// Limits that keep allocation pressure from pushing the system into heavy
+/// swapping or OOM.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(default)]
+pub struct PressureGuard {
+    /// Stop before a chunk would take available memory below this
+    pub floor_mb: u64,
+    /// Never cycle more than this percent of total RAM in one clean
+    pub max_commit_percent: u8,
+}
+
+impl Default for PressureGuard {
+    fn default() -> Self {
+        Self {
+            floor_mb: 512,
+            max_commit_percent: 50,
+        }
+    }
+}
+
+/// Why allocation pressure stopped.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum StopReason {
+    /// Cycled the whole target
+    TargetReached,
+    /// The next chunk would have taken available memory below the floor
+    BelowFloor { available_mb: u64, floor_mb: u64 },
+    /// The target was larger than `max_commit_percent` of total RAM
+    CommitCap { cap_mb: u64 },
+    /// The OS refused to commit the next chunk
+    AllocationFailed,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::TargetReached => write!(f, "target reached"),
+            StopReason::BelowFloor {
+                available_mb,
+                floor_mb,
+            } => write!(
+                f,
+                "available memory ({} MB) near the {} MB floor",
+                available_mb, floor_mb
+            ),
+            StopReason::CommitCap { cap_mb } => {
+                write!(f, "capped at {} MB of total RAM", cap_mb)
+            }
+            StopReason::AllocationFailed => write!(f, "the OS refused to commit more memory"),
+        }
+    }
+}
+
+/// A nonzero target below one chunk is rounded up to one chunk, so a small
+/// target still cycles something.
+fn round_up_target(target_mb: u64) -> u64 {
+    if target_mb > 0 {
+        target_mb.max(CHUNK_MB)
+    } else {
+        0
+    }
+}
+
+/// MB below `available_mb` and above the floor that whole chunks can use.
+fn room_mb(available_mb: u64, guard: &PressureGuard) -> u64 {
+    available_mb.saturating_sub(guard.floor_mb) / CHUNK_MB * CHUNK_MB
+}
+
+/// What `run` would cycle from `memory`, in whole chunks: it stops at the
+/// target, the commit cap or the available floor, and can free no more
+/// than the standby list holds.
+fn estimate_mb(target_mb: u64, guard: &PressureGuard, memory: &MemoryInfo) -> u64 {
+    let target_mb = round_up_target(target_mb);
+    let cap_mb = memory.total_mb * guard.max_commit_percent.min(100) as u64 / 100;
+    let chunks_mb = target_mb.min(cap_mb) / CHUNK_MB * CHUNK_MB;
+    let cache_mb = memory.standby_mb.unwrap_or(memory.cache_mb);
+    chunks_mb
+        .min(room_mb(memory.available_mb, guard))
+        .min(cache_mb)
+}
+
+/// Cycle up to `target_mb` through `cycle_chunk` one chunk at a time,
+/// re-reading `source` before each chunk so the guard sees current memory.
+/// A target below one chunk is rounded up to one. `cycle_chunk` commits, touches and releases the given number of bytes,
+/// returning false if the commit failed. Returns the MB cycled and why the
+/// loop stopped.
+pub fn run(
+    target_mb: u64,
+    guard: &PressureGuard,
+    source: &dyn MemorySource,
+    control: &mut Control,
+    mut cycle_chunk: impl FnMut(usize) -> bool,
+) -> Result<(u64, StopReason)> {
+    let total_mb = source.read()?.total_mb;
+    let cap_mb = total_mb * guard.max_commit_percent.min(100) as u64 / 100;
+    let target_mb = round_up_target(target_mb);
+    let mut processed_mb = 0;
+
+    loop {
+        if processed_mb + CHUNK_MB > target_mb {
+            return Ok((processed_mb, StopReason::TargetReached));
+        }
+        if processed_mb + CHUNK_MB > cap_mb {
+            return Ok((processed_mb, StopReason::CommitCap { cap_mb }));
+        }
+        control.check()?;
+
+        let available_mb = source.read()?.available_mb;
+        if room_mb(available_mb, guard) == 0 {
+            let reason = StopReason::BelowFloor {
+                available_mb,
+                floor_mb: guard.floor_mb,
+            };
+            return Ok((processed_mb, reason));
+        }
+        if !cycle_chunk((CHUNK_MB * 1024 * 1024) as usize) {
+            return Ok((processed_mb, StopReason::AllocationFailed));
+        }
+        processed_mb += CHUNK_MB;
+        control.report(Phase::Cleaning, processed_mb);
+
+        // Small delay to not overwhelm system
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Windows: commit and release memory in chunks to push the standby list
+/// out, within the limits of `guard`.
+pub struct AllocationPressure {
+    pub guard: PressureGuard,
+}
+
+impl CleaningStrategy for AllocationPressure {
+    fn name(&self) -> &'static str {
+        "alloc-pressure"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            supported: cfg!(target_os = "windows"),
+            targeted: true,
+            per_process: false,
+        }
+    }
+
+    fn requires_privilege(&self) -> bool {
+        false
+    }
+
+    fn estimate(&self, target_mb: u64, memory: &MemoryInfo) -> Result<u64> {
+        if !cfg!(target_os = "windows") {
+            return Err(unsupported());
+        }
+        Ok(estimate_mb(target_mb, &self.guard, memory))
+    }
+
+    #[cfg(target_os = "windows")]
+    fn execute(
+        &self,
+        target_mb: u64,
+        _config: &Config,
+        source: &dyn MemorySource,
+        control: &mut Control,
+    ) -> Result<Outcome> {
+        let (processed_mb, stop_reason) = run(
+            target_mb,
+            &self.guard,
+            source,
+            control,
+            super::win32::cycle_chunk,
+        )?;
+        Ok(Outcome {
+            processed_mb,
+            stop_reason: Some(stop_reason),
+            ..Outcome::default()
+        })
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn execute(
+        &self,
+        _target_mb: u64,
+        _config: &Config,
+        _source: &dyn MemorySource,
+        _control: &mut Control,
+    ) -> Result<Outcome> {
+        Err(unsupported())
+    }
+}
+
+fn unsupported() -> Error {
+    Error::unsupported("Allocation pressure is only supported on Windows")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::CancelToken;
+    use crate::memory::tests::memory;
+    use crate::source::ScriptedSource;
+
+    fn run_with(
+        target_mb: u64,
+        guard: PressureGuard,
+        source: &ScriptedSource,
+        mut cycle_chunk: impl FnMut(usize) -> bool,
+    ) -> Result<(u64, StopReason)> {
+        let cancel = CancelToken::new();
+        let mut on_progress = |_: &_| {};
+        let mut control = Control::new(&cancel, &mut on_progress, target_mb);
+        run(target_mb, &guard, source, &mut control, &mut cycle_chunk)
+    }
+
+    #[test]
+    fn stops_at_the_target() {
+        let source = ScriptedSource::new(vec![memory(8000, 6000, 3000)]);
+        let mut chunks = 0;
+        let result = run_with(300, PressureGuard::default(), &source, |bytes| {
+            assert_eq!(bytes as u64, CHUNK_MB * 1024 * 1024);
+            chunks += 1;
+            true
+        });
+        assert_eq!(result.unwrap(), (300, StopReason::TargetReached));
+        assert_eq!(chunks, 3);
+    }
+
+    #[test]
+    fn rounds_a_small_target_up_to_one_chunk() {
+        let source = ScriptedSource::new(vec![memory(8000, 6000, 3000)]);
+        let result = run_with(30, PressureGuard::default(), &source, |_| true);
+        assert_eq!(result.unwrap(), (CHUNK_MB, StopReason::TargetReached));
+        let result = run_with(0, PressureGuard::default(), &source, |_| true);
+        assert_eq!(result.unwrap(), (0, StopReason::TargetReached));
+    }
+
+    #[test]
+    fn stops_at_the_commit_cap() {
+        let source = ScriptedSource::new(vec![memory(8000, 6000, 3000)]);
+        let guard = PressureGuard {
+            floor_mb: 512,
+            max_commit_percent: 5,
+        };
+        let result = run_with(1000, guard, &source, |_| true);
+        assert_eq!(
+            result.unwrap(),
+            (400, StopReason::CommitCap { cap_mb: 400 })
+        );
+    }
+
+    #[test]
+    fn stops_before_a_chunk_would_cross_the_floor() {
+        // The first read is for total RAM, then one before each chunk
+        let source = ScriptedSource::new(vec![
+            memory(8000, 1000, 3000),
+            memory(8000, 1000, 3000),
+            memory(8000, 700, 3000),
+            memory(8000, 600, 3000),
+        ]);
+        let result = run_with(1000, PressureGuard::default(), &source, |_| true);
+        assert_eq!(
+            result.unwrap(),
+            (
+                200,
+                StopReason::BelowFloor {
+                    available_mb: 600,
+                    floor_mb: 512
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn stops_when_a_commit_fails() {
+        let source = ScriptedSource::new(vec![memory(8000, 6000, 3000)]);
+        let mut chunks = 0;
+        let result = run_with(1000, PressureGuard::default(), &source, |_| {
+            chunks += 1;
+            chunks < 2
+        });
+        assert_eq!(result.unwrap(), (100, StopReason::AllocationFailed));
+    }
+
+    #[test]
+    fn stops_when_cancelled() {
+        let source = ScriptedSource::new(vec![memory(8000, 6000, 3000)]);
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let mut on_progress = |_: &_| {};
+        let mut control = Control::new(&cancel, &mut on_progress, 300);
+        let result = run(
+            300,
+            &PressureGuard::default(),
+            &source,
+            &mut control,
+            |_| true,
+        );
+        assert_eq!(result, Err(Error::Cancelled));
+    }
+
+    #[test]
+    fn estimate_uses_the_same_bounds_as_run() {
+        let guard = PressureGuard::default();
+        // Less than a chunk above the floor: run would not cycle anything
+        assert_eq!(estimate_mb(1000, &guard, &memory(8000, 611, 3000)), 0);
+        assert_eq!(estimate_mb(1000, &guard, &memory(8000, 762, 3000)), 200);
+        assert_eq!(estimate_mb(250, &guard, &memory(8000, 6000, 3000)), 200);
+        assert_eq!(estimate_mb(30, &guard, &memory(8000, 6000, 3000)), 100);
+        assert_eq!(estimate_mb(1000, &guard, &memory(8000, 6000, 150)), 150);
+    }
+}
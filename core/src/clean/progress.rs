@@ -0,0 +1,96 @@
+// Progress reporting and cancellation for running cleans
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// What a clean is doing right now.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Taking the before or after snapshot
+    Measuring,
+    /// Flushing dirty pages before dropping caches
+    Syncing,
+    /// Running the strategy itself
+    Cleaning,
+    /// Trimming our own working set
+    Trimming,
+    Finished,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    pub phase: Phase,
+    /// 0-100, from `processed_mb` against `target_mb`
+    pub percent: f32,
+    pub processed_mb: u64,
+    pub target_mb: u64,
+}
+
+/// Shared flag a clean checks between steps; clones share the flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    /// `Err(Error::Cancelled)` once cancelled, for use with `?`.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Cancellation and progress callback passed down to the strategies.
+pub struct Control<'a> {
+    cancel: &'a CancelToken,
+    on_progress: &'a mut dyn FnMut(&Progress),
+    target_mb: u64,
+}
+
+impl<'a> Control<'a> {
+    pub fn new(
+        cancel: &'a CancelToken,
+        on_progress: &'a mut dyn FnMut(&Progress),
+        target_mb: u64,
+    ) -> Self {
+        Self {
+            cancel,
+            on_progress,
+            target_mb,
+        }
+    }
+
+    pub fn check(&self) -> Result<()> {
+        self.cancel.check()
+    }
+
+    pub fn report(&mut self, phase: Phase, processed_mb: u64) {
+        let percent = match phase {
+            Phase::Finished => 100.0,
+            _ if self.target_mb == 0 => 0.0,
+            _ => (processed_mb as f32 / self.target_mb as f32 * 100.0).min(100.0),
+        };
+        (self.on_progress)(&Progress {
+            phase,
+            percent,
+            processed_mb,
+            target_mb: self.target_mb,
+        });
+    }
+}
@@ -0,0 +1,64 @@
+// The CleaningStrategy trait every way of freeing memory implements
+
+use super::progress::Control;
+use super::{CgroupReclaimStats, StepError, StopReason};
+use crate::error::Result;
+use crate::process::ProcessInfo;
+use crate::{Config, MemoryInfo, MemorySource};
+use serde::{Deserialize, Serialize};
+
+/// What a strategy can do, for choosing and explaining one.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub struct Capabilities {
+    /// Runs on this platform
+    pub supported: bool,
+    /// Frees about `target_mb` rather than everything it can reach
+    pub targeted: bool,
+    /// Acts on particular processes, so the process rules apply to it
+    pub per_process: bool,
+}
+
+/// What a strategy reports before snapshots are compared.
+#[derive(Default, Debug)]
+pub struct Outcome {
+    /// MB pushed through (allocated, or asked the kernel to reclaim)
+    pub processed_mb: u64,
+    pub cgroup: Option<CgroupReclaimStats>,
+    pub stop_reason: Option<StopReason>,
+    /// Side steps that failed without failing the strategy
+    pub errors: Vec<StepError>,
+    /// One entry per step for a pipeline
+    pub steps: Vec<super::pipeline::StepReport>,
+}
+
+/// One way of freeing memory. `Strategy` is the serializable choice between
+/// them and `Strategy::build` returns the implementation, so a new method
+/// is a new implementation plus a `Strategy` variant.
+pub trait CleaningStrategy {
+    /// Short name, as the spec starts with it
+    fn name(&self) -> &'static str;
+
+    fn capabilities(&self) -> Capabilities;
+
+    /// Needs root or write access to a system file the user may lack
+    fn requires_privilege(&self) -> bool;
+
+    /// Whether it acts on `process` directly.
+    fn affects(&self, _process: &ProcessInfo) -> bool {
+        false
+    }
+
+    /// MB it could free now towards `target_mb`. Fails with the error the
+    /// clean would fail with if it could not run, without touching memory.
+    fn estimate(&self, target_mb: u64, memory: &MemoryInfo) -> Result<u64>;
+
+    /// Free up to `target_mb`. `config` supplies the thresholds pipeline
+    /// stop conditions check.
+    fn execute(
+        &self,
+        target_mb: u64,
+        config: &Config,
+        source: &dyn MemorySource,
+        control: &mut Control,
+    ) -> Result<Outcome>;
+}
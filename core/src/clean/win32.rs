@@ -0,0 +1,42 @@
+// Windows allocation-pressure cleaning
+
+use crate::error::Result;
+use crate::source::win32::os_error;
+use windows::Win32::System::Memory::*;
+use windows::Win32::System::ProcessStatus::*;
+use windows::Win32::System::Threading::*;
+
+/// Commit `bytes`, touch every page so it is backed by physical memory,
+/// then release it. Returns false if the commit failed.
+pub fn cycle_chunk(bytes: usize) -> bool {
+    unsafe {
+        let ptr = VirtualAlloc(None, bytes, MEM_COMMIT | MEM_RESERVE, PAGE_READWRITE);
+        if ptr.is_null() {
+            return false;
+        }
+
+        // Write to memory to ensure it's committed
+        std::ptr::write_bytes(ptr as *mut u8, 0, bytes);
+
+        // Free immediately
+        let _ = VirtualFree(ptr, 0, MEM_RELEASE);
+        true
+    }
+}
+
+/// Clear the working set of the current process.
+pub fn empty_working_set() -> Result<()> {
+    unsafe { EmptyWorkingSet(GetCurrentProcess()) }
+        .map_err(|e| os_error("EmptyWorkingSet failed", e))
+}
+
+/// Working set of the current process in MB.
+pub fn working_set_mb() -> Option<u64> {
+    let cb = std::mem::size_of::<PROCESS_MEMORY_COUNTERS>() as u32;
+    let mut counters = PROCESS_MEMORY_COUNTERS {
+        cb,
+        ..Default::default()
+    };
+    unsafe { GetProcessMemoryInfo(GetCurrentProcess(), &mut counters, cb) }.ok()?;
+    Some(counters.WorkingSetSize as u64 / (1024 * 1024))
+}
@@ -0,0 +1,87 @@
+// Trimming this process's own working set
+
+use super::progress::{Control, Phase};
+use super::strategy::{Capabilities, CleaningStrategy, Outcome};
+use crate::error::{Error, Result};
+use crate::{Config, MemoryInfo, MemorySource};
+
+/// Windows: `EmptyWorkingSet` on our own process, typically after
+/// allocation pressure has pulled pages into it.
+pub struct TrimWorkingSet;
+
+impl CleaningStrategy for TrimWorkingSet {
+    fn name(&self) -> &'static str {
+        "trim-working-set"
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities {
+            supported: cfg!(target_os = "windows"),
+            targeted: false,
+            per_process: false,
+        }
+    }
+
+    fn requires_privilege(&self) -> bool {
+        false
+    }
+
+    fn estimate(&self, _target_mb: u64, _memory: &MemoryInfo) -> Result<u64> {
+        if !cfg!(target_os = "windows") {
+            return Err(unsupported());
+        }
+        Ok(working_set_mb().unwrap_or(0))
+    }
+
+    #[cfg(target_os = "windows")]
+    fn execute(
+        &self,
+        _target_mb: u64,
+        _config: &Config,
+        _source: &dyn MemorySource,
+        control: &mut Control,
+    ) -> Result<Outcome> {
+        control.report(Phase::Trimming, 0);
+        super::win32::empty_working_set()?;
+        Ok(Outcome::default())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn execute(
+        &self,
+        _target_mb: u64,
+        _config: &Config,
+        _source: &dyn MemorySource,
+        control: &mut Control,
+    ) -> Result<Outcome> {
+        control.report(Phase::Trimming, 0);
+        Err(unsupported())
+    }
+}
+
+fn unsupported() -> Error {
+    Error::unsupported("Trimming the working set is only supported on Windows")
+}
+
+/// This process's working set in MB.
+#[cfg(target_os = "windows")]
+pub fn working_set_mb() -> Option<u64> {
+    super::win32::working_set_mb()
+}
+
+/// This process's resident set in MB, from VmRSS.
+#[cfg(not(target_os = "windows"))]
+pub fn working_set_mb() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|rest| {
+            rest.trim()
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()
+        })
+        .map(|kb| kb / 1024)
+}
@@ -0,0 +1,728 @@
+// User-facing cleaning configuration
+
+use crate::clean::Strategy;
+use crate::error::{Error, FieldError, Result};
+use crate::process::rules::{format_rule_list, parse_rule_list};
+use crate::process::ProcessRules;
+use crate::threshold::ThresholdMode;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Current on-disk config format. Bump when a change needs migration.
+pub const CONFIG_VERSION: u32 = 2;
+
+const CONFIG_FILE_NAME: &str = "config.json";
+
+/// Gap kept between the thresholds when normalizing, matching the UI
+/// slider step.
+pub const MIN_THRESHOLD_GAP_MB: u64 = 128;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// Which of the threshold fields apply and what they are compared to
+    pub threshold_mode: ThresholdMode,
+    /// Cache MB (`absolute_mb`) or available MB (`available_below_mb`)
+    pub start_threshold_mb: u64,
+    pub stop_threshold_mb: u64,
+    /// Percent of total memory (`percent_of_total`)
+    pub start_threshold_percent: f32,
+    pub stop_threshold_percent: f32,
+    pub auto_clean_enabled: bool,
+    /// Strategy used by auto-clean and by cleans that do not name one
+    pub strategy: Strategy,
+    /// Serve Prometheus metrics on 127.0.0.1 at this port; off when `None`
+    pub metrics_port: Option<u16>,
+    /// How often the GUI samples memory for live updates
+    pub update_interval_ms: u64,
+    /// Skip live updates until a field moves by at least this much
+    pub update_min_change_mb: u64,
+    /// Processes strategies and trimming may or may not act on
+    pub process_rules: ProcessRules,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            threshold_mode: ThresholdMode::AbsoluteMb,
+            start_threshold_mb: 2048,
+            stop_threshold_mb: 1024,
+            start_threshold_percent: 25.0,
+            stop_threshold_percent: 12.5,
+            auto_clean_enabled: true,
+            strategy: Strategy::default(),
+            metrics_port: None,
+            update_interval_ms: 1000,
+            update_min_change_mb: 16,
+            process_rules: ProcessRules::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Names accepted by `get` and `set`.
+    pub const KEYS: &'static [&'static str] = &[
+        "threshold_mode",
+        "start_threshold_mb",
+        "stop_threshold_mb",
+        "start_threshold_percent",
+        "stop_threshold_percent",
+        "auto_clean_enabled",
+        "strategy",
+        "metrics_port",
+        "update_interval_ms",
+        "update_min_change_mb",
+        "process_allow",
+        "process_deny",
+    ];
+
+    /// Read a single setting as text.
+    pub fn get(&self, key: &str) -> Result<String> {
+        match key {
+            "threshold_mode" => Ok(self.threshold_mode.to_string()),
+            "start_threshold_mb" => Ok(self.start_threshold_mb.to_string()),
+            "stop_threshold_mb" => Ok(self.stop_threshold_mb.to_string()),
+            "start_threshold_percent" => Ok(self.start_threshold_percent.to_string()),
+            "stop_threshold_percent" => Ok(self.stop_threshold_percent.to_string()),
+            "auto_clean_enabled" => Ok(self.auto_clean_enabled.to_string()),
+            "strategy" => Ok(self.strategy.to_string()),
+            "metrics_port" => Ok(self
+                .metrics_port
+                .map_or_else(|| "off".to_string(), |port| port.to_string())),
+            "update_interval_ms" => Ok(self.update_interval_ms.to_string()),
+            "update_min_change_mb" => Ok(self.update_min_change_mb.to_string()),
+            "process_allow" => Ok(format_rule_list(&self.process_rules.allow)),
+            "process_deny" => Ok(format_rule_list(&self.process_rules.deny)),
+            _ => Err(Error::invalid_config(format!(
+                "Unknown config key: {}",
+                key
+            ))),
+        }
+    }
+
+    /// Update a single setting from text.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let invalid = || {
+            Error::invalid_fields(vec![FieldError::new(
+                key,
+                format!("invalid value {:?}", value),
+            )])
+        };
+        match key {
+            "threshold_mode" => {
+                self.threshold_mode = ThresholdMode::parse(value)
+                    .map_err(|e| Error::invalid_fields(vec![FieldError::new(key, e.to_string())]))?
+            }
+            "start_threshold_mb" => {
+                self.start_threshold_mb = value.parse().map_err(|_| invalid())?
+            }
+            "start_threshold_percent" => {
+                self.start_threshold_percent = value.parse().map_err(|_| invalid())?
+            }
+            "stop_threshold_percent" => {
+                self.stop_threshold_percent = value.parse().map_err(|_| invalid())?
+            }
+            "stop_threshold_mb" => self.stop_threshold_mb = value.parse().map_err(|_| invalid())?,
+            "auto_clean_enabled" => {
+                self.auto_clean_enabled = value.parse().map_err(|_| invalid())?
+            }
+            "strategy" => {
+                self.strategy = Strategy::parse(value)
+                    .map_err(|e| Error::invalid_fields(vec![FieldError::new(key, e.to_string())]))?
+            }
+            "metrics_port" => {
+                self.metrics_port = match value {
+                    "off" => None,
+                    port => Some(port.parse().map_err(|_| invalid())?),
+                }
+            }
+            "update_interval_ms" => {
+                self.update_interval_ms = value.parse().map_err(|_| invalid())?
+            }
+            "update_min_change_mb" => {
+                self.update_min_change_mb = value.parse().map_err(|_| invalid())?
+            }
+            "process_allow" => {
+                self.process_rules.allow = parse_rule_list(value)
+                    .map_err(|e| Error::invalid_fields(vec![FieldError::new(key, e.to_string())]))?
+            }
+            "process_deny" => {
+                self.process_rules.deny = parse_rule_list(value)
+                    .map_err(|e| Error::invalid_fields(vec![FieldError::new(key, e.to_string())]))?
+            }
+            _ => {
+                return Err(Error::invalid_config(format!(
+                    "Unknown config key: {}",
+                    key
+                )))
+            }
+        }
+        Ok(())
+    }
+
+    /// Check cross-field rules for the active threshold mode. `total_mb` is
+    /// the machine's physical memory; pass `None` when it cannot be read to
+    /// skip the checks that need it.
+    pub fn validate(&self, total_mb: Option<u64>) -> Result<()> {
+        let mut fields = Vec::new();
+        let mut reject =
+            |field: &str, message: String| fields.push(FieldError::new(field, message));
+
+        match self.threshold_mode {
+            ThresholdMode::AbsoluteMb => {
+                if self.start_threshold_mb == 0 {
+                    reject("start_threshold_mb", "must be greater than 0".to_string());
+                }
+                if self.stop_threshold_mb >= self.start_threshold_mb {
+                    reject(
+                        "stop_threshold_mb",
+                        format!(
+                            "must be below the start threshold ({} MB)",
+                            self.start_threshold_mb
+                        ),
+                    );
+                }
+                if let Some(total_mb) = total_mb.filter(|&t| self.start_threshold_mb > t) {
+                    reject(
+                        "start_threshold_mb",
+                        format!("exceeds physical memory ({} MB)", total_mb),
+                    );
+                }
+            }
+            ThresholdMode::PercentOfTotal => {
+                let start = self.start_threshold_percent;
+                let stop = self.stop_threshold_percent;
+                if !(start > 0.0 && start <= 100.0) {
+                    reject(
+                        "start_threshold_percent",
+                        "must be above 0 and at most 100".to_string(),
+                    );
+                }
+                if !(stop >= 0.0 && stop < start) {
+                    reject(
+                        "stop_threshold_percent",
+                        format!(
+                            "must be at least 0 and below the start threshold ({}%)",
+                            start
+                        ),
+                    );
+                }
+            }
+            ThresholdMode::AvailableBelowMb => {
+                if self.start_threshold_mb == 0 {
+                    reject("start_threshold_mb", "must be greater than 0".to_string());
+                }
+                if self.stop_threshold_mb <= self.start_threshold_mb {
+                    reject(
+                        "stop_threshold_mb",
+                        format!(
+                            "must be above the start threshold ({} MB available)",
+                            self.start_threshold_mb
+                        ),
+                    );
+                }
+                if let Some(total_mb) = total_mb.filter(|&t| self.stop_threshold_mb > t) {
+                    reject(
+                        "stop_threshold_mb",
+                        format!("exceeds physical memory ({} MB)", total_mb),
+                    );
+                }
+            }
+        }
+        let steps: Vec<&Strategy> = match &self.strategy {
+            Strategy::Pipeline { steps } => {
+                if steps.is_empty() {
+                    reject("strategy", "pipeline has no steps".to_string());
+                }
+                steps.iter().map(|step| &step.strategy).collect()
+            }
+            strategy => vec![strategy],
+        };
+        for strategy in steps {
+            match strategy {
+                Strategy::CgroupReclaim { path } if path.as_os_str().is_empty() => {
+                    reject("strategy", "cgroup path is empty".to_string());
+                }
+                Strategy::AllocationPressure { guard } => {
+                    if guard.max_commit_percent == 0 || guard.max_commit_percent > 100 {
+                        reject(
+                            "strategy",
+                            "allocation pressure max must be 1-100% of RAM".to_string(),
+                        );
+                    }
+                    if let Some(total_mb) = total_mb.filter(|&t| guard.floor_mb >= t) {
+                        reject(
+                            "strategy",
+                            format!(
+                                "allocation pressure floor exceeds physical memory ({} MB)",
+                                total_mb
+                            ),
+                        );
+                    }
+                }
+                Strategy::Pipeline { .. } => {
+                    reject("strategy", "pipelines cannot be nested".to_string());
+                }
+                _ => {}
+            }
+        }
+        if self.metrics_port == Some(0) {
+            reject("metrics_port", "must be between 1 and 65535".to_string());
+        }
+        if !(100..=60_000).contains(&self.update_interval_ms) {
+            reject(
+                "update_interval_ms",
+                "must be between 100 and 60000".to_string(),
+            );
+        }
+
+        if fields.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::invalid_fields(fields))
+        }
+    }
+
+    /// Clamp thresholds for the active mode into range instead of rejecting
+    /// them, moving the threshold that has to give way to
+    /// `MIN_THRESHOLD_GAP_MB` (or one percent) from the other where there is
+    /// room for that.
+    pub fn normalized(&self, total_mb: Option<u64>) -> Config {
+        let mut config = self.clone();
+        let total_mb = total_mb.unwrap_or(u64::MAX);
+
+        match config.threshold_mode {
+            ThresholdMode::AbsoluteMb => {
+                config.start_threshold_mb = config.start_threshold_mb.max(1).min(total_mb);
+                if config.stop_threshold_mb >= config.start_threshold_mb {
+                    config.stop_threshold_mb = config
+                        .start_threshold_mb
+                        .saturating_sub(MIN_THRESHOLD_GAP_MB);
+                }
+            }
+            ThresholdMode::PercentOfTotal => {
+                config.start_threshold_percent = config.start_threshold_percent.clamp(1.0, 100.0);
+                if config.stop_threshold_percent >= config.start_threshold_percent {
+                    config.stop_threshold_percent = config.start_threshold_percent - 1.0;
+                }
+                config.stop_threshold_percent = config.stop_threshold_percent.max(0.0);
+            }
+            ThresholdMode::AvailableBelowMb => {
+                // Stop has to stay above a start of at least 1 MB
+                config.stop_threshold_mb = config.stop_threshold_mb.max(2).min(total_mb);
+                config.start_threshold_mb = config.start_threshold_mb.max(1);
+                if config.start_threshold_mb >= config.stop_threshold_mb {
+                    config.start_threshold_mb = config
+                        .stop_threshold_mb
+                        .saturating_sub(MIN_THRESHOLD_GAP_MB)
+                        .max(1);
+                }
+            }
+        }
+        config
+    }
+}
+
+/// Versioned envelope written to disk.
+#[derive(Serialize, Deserialize)]
+struct ConfigFile {
+    version: u32,
+    config: Config,
+}
+
+/// Only the version, to pick a parser before reading the rest.
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: Option<u32>,
+}
+
+/// Bring a config saved as `version` up to `CONFIG_VERSION`.
+fn migrate(mut config: Config, version: u32) -> Config {
+    // Before version 2, allocation pressure also trimmed our working set;
+    // that is now a separate step
+    if version < 2 {
+        if let Strategy::AllocationPressure { guard } = config.strategy {
+            config.strategy = Strategy::pressure_then_trim(guard);
+        }
+    }
+    config
+}
+
+/// Directory for per-user settings: %APPDATA%\MemoryCacheManager on
+/// Windows, ~/Library/Application Support/MemoryCacheManager on macOS and
+/// $XDG_CONFIG_HOME/memory-cache-manager (or ~/.config/...) elsewhere.
+pub fn config_dir() -> Option<PathBuf> {
+    let env_dir = |key: &str| {
+        std::env::var_os(key)
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+    };
+
+    if cfg!(target_os = "windows") {
+        env_dir("APPDATA").map(|dir| dir.join("MemoryCacheManager"))
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library/Application Support/MemoryCacheManager"))
+    } else {
+        env_dir("XDG_CONFIG_HOME")
+            .or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+            .map(|dir| dir.join("memory-cache-manager"))
+    }
+}
+
+/// Loads and saves `Config` as a versioned JSON file. Saves go through a
+/// temporary file and a rename, so a crash mid-write leaves the previous
+/// config intact.
+#[derive(Clone, Debug)]
+pub struct ConfigStore {
+    path: PathBuf,
+}
+
+impl ConfigStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The store in the platform config directory.
+    pub fn default_location() -> Result<Self> {
+        config_dir()
+            .map(|dir| Self::new(dir.join(CONFIG_FILE_NAME)))
+            .ok_or_else(|| Error::unsupported("Could not determine the config directory"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Load the config, or defaults if nothing has been saved yet.
+    pub fn load(&self) -> Result<Config> {
+        let contents = match fs::read_to_string(&self.path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => {
+                return Err(Error::io(
+                    format!("Failed to read {}", self.path.display()),
+                    e,
+                ))
+            }
+        };
+        let invalid = |e: serde_json::Error| {
+            Error::invalid_config(format!("Invalid config {}: {}", self.path.display(), e))
+        };
+
+        let probe: VersionProbe = serde_json::from_str(&contents).map_err(invalid)?;
+        match probe.version {
+            // Unversioned files are a bare Config from before versioning
+            None => Ok(migrate(
+                serde_json::from_str(&contents).map_err(invalid)?,
+                0,
+            )),
+            Some(version) if version <= CONFIG_VERSION => {
+                let file: ConfigFile = serde_json::from_str(&contents).map_err(invalid)?;
+                Ok(migrate(file.config, version))
+            }
+            Some(version) => Err(Error::invalid_config(format!(
+                "Config {} has version {}, newer than supported version {}",
+                self.path.display(),
+                version,
+                CONFIG_VERSION
+            ))),
+        }
+    }
+
+    /// Atomically replace the saved config.
+    pub fn save(&self, config: &Config) -> Result<()> {
+        let file = ConfigFile {
+            version: CONFIG_VERSION,
+            config: config.clone(),
+        };
+        let contents =
+            serde_json::to_vec_pretty(&file).map_err(|e| Error::invalid_config(e.to_string()))?;
+        let write_err =
+            |e: std::io::Error| Error::io(format!("Failed to write {}", self.path.display()), e);
+
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            fs::create_dir_all(dir).map_err(write_err)?;
+        }
+
+        // Unique per save, so concurrent saves (the GUI and `mcm config
+        // set`) never write the same temporary file
+        static SAVES: AtomicU64 = AtomicU64::new(0);
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            SAVES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp_path = PathBuf::from(tmp_path);
+
+        let written = File::create(&tmp_path)
+            .and_then(|mut tmp| {
+                tmp.write_all(&contents)?;
+                tmp.sync_all()
+            })
+            .and_then(|()| fs::rename(&tmp_path, &self.path));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&tmp_path);
+            return Err(write_err(e));
+        }
+
+        // Persist the rename itself; directories cannot be opened for
+        // syncing on Windows, where rename is already durable.
+        #[cfg(unix)]
+        if let Some(dir) = self.path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)
+                .and_then(|d| d.sync_all())
+                .map_err(write_err)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::PressureGuard;
+
+    fn normalizes_to_valid(mode: ThresholdMode, start_mb: u64, stop_mb: u64, total_mb: u64) {
+        let config = Config {
+            threshold_mode: mode,
+            start_threshold_mb: start_mb,
+            stop_threshold_mb: stop_mb,
+            ..Config::default()
+        };
+        let normalized = config.normalized(Some(total_mb));
+        assert!(
+            normalized.validate(Some(total_mb)).is_ok(),
+            "{:?} {}/{} on {} MB normalized to {}/{}",
+            mode,
+            start_mb,
+            stop_mb,
+            total_mb,
+            normalized.start_threshold_mb,
+            normalized.stop_threshold_mb
+        );
+    }
+
+    #[test]
+    fn normalized_configs_validate() {
+        for mode in [ThresholdMode::AbsoluteMb, ThresholdMode::AvailableBelowMb] {
+            normalizes_to_valid(mode, 5000, 100, 16384);
+            normalizes_to_valid(mode, 100, 5000, 16384);
+            normalizes_to_valid(mode, 0, 0, 16384);
+            normalizes_to_valid(mode, 50_000, 40_000, 16384);
+            normalizes_to_valid(mode, 2048, 1024, 64);
+        }
+    }
+
+    #[test]
+    fn normalized_keeps_the_gap_where_there_is_room() {
+        let config = Config {
+            threshold_mode: ThresholdMode::AvailableBelowMb,
+            start_threshold_mb: 5000,
+            stop_threshold_mb: 1000,
+            ..Config::default()
+        }
+        .normalized(Some(16384));
+        assert_eq!(config.stop_threshold_mb, 1000);
+        assert_eq!(config.start_threshold_mb, 1000 - MIN_THRESHOLD_GAP_MB);
+    }
+
+    /// A config store in its own temp directory, removed on drop.
+    struct TempStore(ConfigStore);
+
+    impl TempStore {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("mcm-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(ConfigStore::new(dir.join(CONFIG_FILE_NAME)))
+        }
+
+        fn load(&self, contents: &str) -> Result<Config> {
+            fs::write(self.0.path(), contents).unwrap();
+            self.0.load()
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            if let Some(dir) = self.0.path().parent() {
+                let _ = fs::remove_dir_all(dir);
+            }
+        }
+    }
+
+    #[test]
+    fn migrates_v1_allocation_pressure_to_pressure_then_trim() {
+        let store = TempStore::new("config-v1");
+        let guard = PressureGuard {
+            floor_mb: 900,
+            max_commit_percent: 40,
+        };
+        let v1 = r#"{"version":1,"config":{"strategy":{"kind":"allocation_pressure","guard":{"floor_mb":900,"max_commit_percent":40}}}}"#;
+        assert_eq!(
+            store.load(v1).unwrap().strategy,
+            Strategy::pressure_then_trim(guard)
+        );
+
+        // Unversioned files predate version 2 as well
+        let bare = r#"{"strategy":{"kind":"allocation_pressure","guard":{"floor_mb":900,"max_commit_percent":40}}}"#;
+        assert_eq!(
+            store.load(bare).unwrap().strategy,
+            Strategy::pressure_then_trim(guard)
+        );
+
+        // From version 2 on, a bare allocation_pressure means just that
+        let v2 = r#"{"version":2,"config":{"strategy":{"kind":"allocation_pressure","guard":{"floor_mb":900,"max_commit_percent":40}}}}"#;
+        assert_eq!(
+            store.load(v2).unwrap().strategy,
+            Strategy::AllocationPressure { guard }
+        );
+    }
+
+    #[test]
+    fn saved_configs_load_back() {
+        let store = TempStore::new("config-save");
+        let config = Config {
+            strategy: Strategy::pressure_then_trim(PressureGuard::default()),
+            auto_clean_enabled: true,
+            ..Config::default()
+        };
+        store.0.save(&config).unwrap();
+        assert_eq!(store.0.load().unwrap(), config);
+        assert!(store.load(r#"{"version":99,"config":{}}"#).is_err());
+    }
+
+    /// The fields `validate` rejects, in order.
+    fn rejected(config: Config, total_mb: Option<u64>) -> Vec<String> {
+        match config.validate(total_mb) {
+            Ok(()) => Vec::new(),
+            Err(Error::InvalidConfig { fields, .. }) => {
+                fields.into_iter().map(|f| f.field).collect()
+            }
+            Err(e) => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[test]
+    fn validate_accepts_the_defaults() {
+        assert!(rejected(Config::default(), Some(16384)).is_empty());
+        assert!(rejected(Config::default(), None).is_empty());
+    }
+
+    #[test]
+    fn validate_wants_stop_below_start_in_mb_mode() {
+        let config = |start_mb, stop_mb| Config {
+            start_threshold_mb: start_mb,
+            stop_threshold_mb: stop_mb,
+            ..Config::default()
+        };
+        assert_eq!(rejected(config(1024, 1024), None), ["stop_threshold_mb"]);
+        assert_eq!(rejected(config(1024, 2048), None), ["stop_threshold_mb"]);
+        assert_eq!(
+            rejected(config(0, 0), None),
+            ["start_threshold_mb", "stop_threshold_mb"]
+        );
+    }
+
+    #[test]
+    fn validate_rejects_thresholds_above_physical_memory() {
+        let config = Config {
+            start_threshold_mb: 20_000,
+            stop_threshold_mb: 1024,
+            ..Config::default()
+        };
+        assert_eq!(
+            rejected(config.clone(), Some(16384)),
+            ["start_threshold_mb"]
+        );
+        // Unknown total memory skips the check
+        assert!(rejected(config, None).is_empty());
+
+        let available = Config {
+            threshold_mode: ThresholdMode::AvailableBelowMb,
+            start_threshold_mb: 1024,
+            stop_threshold_mb: 20_000,
+            ..Config::default()
+        };
+        assert_eq!(rejected(available, Some(16384)), ["stop_threshold_mb"]);
+    }
+
+    #[test]
+    fn validate_wants_stop_above_start_in_available_mode() {
+        let config = |start_mb, stop_mb| Config {
+            threshold_mode: ThresholdMode::AvailableBelowMb,
+            start_threshold_mb: start_mb,
+            stop_threshold_mb: stop_mb,
+            ..Config::default()
+        };
+        assert!(rejected(config(1024, 2048), Some(16384)).is_empty());
+        // The absolute_mb ordering is wrong here
+        assert_eq!(rejected(config(2048, 1024), None), ["stop_threshold_mb"]);
+        assert_eq!(rejected(config(1024, 1024), None), ["stop_threshold_mb"]);
+    }
+
+    #[test]
+    fn validate_checks_percentages() {
+        let config = |start, stop| Config {
+            threshold_mode: ThresholdMode::PercentOfTotal,
+            start_threshold_percent: start,
+            stop_threshold_percent: stop,
+            ..Config::default()
+        };
+        assert!(rejected(config(100.0, 0.0), None).is_empty());
+        assert_eq!(
+            rejected(config(f32::NAN, 10.0), None),
+            ["start_threshold_percent", "stop_threshold_percent"]
+        );
+        assert_eq!(
+            rejected(config(50.0, f32::NAN), None),
+            ["stop_threshold_percent"]
+        );
+        assert_eq!(
+            rejected(config(0.0, 0.0), None),
+            ["start_threshold_percent", "stop_threshold_percent"]
+        );
+        assert_eq!(
+            rejected(config(101.0, 50.0), None),
+            ["start_threshold_percent"]
+        );
+        assert_eq!(
+            rejected(config(50.0, -1.0), None),
+            ["stop_threshold_percent"]
+        );
+        assert_eq!(
+            rejected(config(50.0, 50.0), None),
+            ["stop_threshold_percent"]
+        );
+    }
+
+    #[test]
+    fn validate_reports_every_field_with_its_message() {
+        let config = Config {
+            stop_threshold_mb: 4096,
+            metrics_port: Some(0),
+            update_interval_ms: 50,
+            ..Config::default()
+        };
+        let Err(Error::InvalidConfig { message, fields }) = config.validate(None) else {
+            panic!("expected InvalidConfig");
+        };
+        assert_eq!(
+            fields,
+            [
+                FieldError::new(
+                    "stop_threshold_mb",
+                    "must be below the start threshold (2048 MB)"
+                ),
+                FieldError::new("metrics_port", "must be between 1 and 65535"),
+                FieldError::new("update_interval_ms", "must be between 100 and 60000"),
+            ]
+        );
+        assert!(message.starts_with("stop_threshold_mb: must be below"));
+    }
+}
@@ -0,0 +1,122 @@
+// Error type shared by the core library and every frontend
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+
+/// Serialized as `{ "kind": "permission_denied", "message": "..." }` so the
+/// UI can branch on `kind`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Error {
+    /// Not available on this platform or kernel
+    Unsupported { message: String },
+    /// Needs administrator/root or write access the process lacks
+    PermissionDenied { message: String },
+    /// A config value or combination of values is not acceptable; `fields`
+    /// names each offending field when known
+    InvalidConfig {
+        message: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        fields: Vec<FieldError>,
+    },
+    /// A command argument, spec string or file format is malformed
+    InvalidInput { message: String },
+    /// An OS call failed; `code` is errno on Unix and the HRESULT on Windows
+    OsError { code: i32, message: String },
+    /// Another operation holds the resource, e.g. a clean already running
+    Busy { message: String },
+    /// The operation was cancelled before it finished
+    Cancelled,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// One rejected config field, for display next to the matching control.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+impl Error {
+    pub fn unsupported(message: impl Into<String>) -> Self {
+        Error::Unsupported {
+            message: message.into(),
+        }
+    }
+
+    pub fn permission_denied(message: impl Into<String>) -> Self {
+        Error::PermissionDenied {
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_config(message: impl Into<String>) -> Self {
+        Error::InvalidConfig {
+            message: message.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    /// An `InvalidConfig` listing every failing field.
+    pub fn invalid_fields(fields: Vec<FieldError>) -> Self {
+        let message = fields
+            .iter()
+            .map(|f| format!("{}: {}", f.field, f.message))
+            .collect::<Vec<_>>()
+            .join("; ");
+        Error::InvalidConfig { message, fields }
+    }
+
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        Error::InvalidInput {
+            message: message.into(),
+        }
+    }
+
+    pub fn busy(message: impl Into<String>) -> Self {
+        Error::Busy {
+            message: message.into(),
+        }
+    }
+
+    /// Classify an I/O error, prefixing the message with `context` (usually
+    /// "Failed to read <path>").
+    pub fn io(context: impl fmt::Display, e: io::Error) -> Self {
+        let message = format!("{}: {}", context, e);
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied { message },
+            io::ErrorKind::Unsupported => Error::Unsupported { message },
+            _ => Error::OsError {
+                code: e.raw_os_error().unwrap_or(-1),
+                message,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Unsupported { message }
+            | Error::PermissionDenied { message }
+            | Error::InvalidConfig { message, .. }
+            | Error::InvalidInput { message }
+            | Error::OsError { message, .. }
+            | Error::Busy { message } => f.write_str(message),
+            Error::Cancelled => f.write_str("Operation cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,472 @@
+// Export recorded samples and cleans as CSV, JSON Lines or JSON
+
+use crate::error::{Error, Result};
+use crate::history::{CleanMarker, HistoryRange, Sample};
+use serde_json::{Map, Value};
+use std::fmt::Write as _;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    JsonLines,
+    Json,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "csv" => Ok(ExportFormat::Csv),
+            "jsonl" => Ok(ExportFormat::JsonLines),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(Error::invalid_input(format!(
+                "Unknown export format: {} (expected csv, jsonl or json)",
+                value
+            ))),
+        }
+    }
+}
+
+/// Unit memory columns are written in; also the column name suffix.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Unit {
+    Bytes,
+    Kb,
+    #[default]
+    Mb,
+    Gb,
+}
+
+impl Unit {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "b" | "bytes" => Ok(Unit::Bytes),
+            "kb" => Ok(Unit::Kb),
+            "mb" => Ok(Unit::Mb),
+            "gb" => Ok(Unit::Gb),
+            _ => Err(Error::invalid_input(format!(
+                "Unknown unit: {} (expected bytes, kb, mb or gb)",
+                value
+            ))),
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Unit::Bytes => "bytes",
+            Unit::Kb => "kb",
+            Unit::Mb => "mb",
+            Unit::Gb => "gb",
+        }
+    }
+
+    fn convert(self, mb: u64) -> Value {
+        match self {
+            Unit::Bytes => Value::from(mb * 1024 * 1024),
+            Unit::Kb => Value::from(mb * 1024),
+            Unit::Mb => Value::from(mb),
+            Unit::Gb => Value::from(mb as f64 / 1024.0),
+        }
+    }
+}
+
+/// Which records to export.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Dataset {
+    #[default]
+    Samples,
+    /// The clean log: a summary per clean, not the full `CleanReport`
+    Cleans,
+    /// Both; not available as CSV, which holds a single table
+    All,
+}
+
+impl Dataset {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "samples" => Ok(Dataset::Samples),
+            "cleans" => Ok(Dataset::Cleans),
+            "all" => Ok(Dataset::All),
+            _ => Err(Error::invalid_input(format!(
+                "Unknown dataset: {} (expected samples, cleans or all)",
+                value
+            ))),
+        }
+    }
+}
+
+/// Sample columns in output order. Memory columns get the unit appended,
+/// e.g. `cache` is written as `cache_mb`.
+pub const SAMPLE_COLUMNS: &[&str] = &[
+    "time",
+    "timestamp_ms",
+    "total",
+    "available",
+    "used",
+    "cache",
+    "usage_percent",
+    "page_cache",
+    "buffers",
+    "reclaimable_slab",
+    "shared",
+    "standby",
+    "modified",
+];
+
+pub const CLEAN_COLUMNS: &[&str] = &[
+    "time",
+    "timestamp_ms",
+    "cleaned",
+    "automatic",
+    "duration_ms",
+];
+
+const MEMORY_COLUMNS: &[&str] = &[
+    "total",
+    "available",
+    "used",
+    "cache",
+    "page_cache",
+    "buffers",
+    "reclaimable_slab",
+    "shared",
+    "standby",
+    "modified",
+    "cleaned",
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub dataset: Dataset,
+    pub unit: Unit,
+    /// Columns to include, in `SAMPLE_COLUMNS`/`CLEAN_COLUMNS` order; all
+    /// when `None`
+    pub columns: Option<Vec<String>>,
+}
+
+fn csv_cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(v) if v.contains([',', '"', '\n']) => {
+            format!("\"{}\"", v.replace('"', "\"\""))
+        }
+        Value::String(v) => v.clone(),
+        v => v.to_string(),
+    }
+}
+
+/// UTC ISO 8601 time for a Unix timestamp in ms.
+pub fn format_time(timestamp_ms: u64) -> String {
+    let secs = timestamp_ms / 1000;
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem / 60 % 60,
+        rem % 60,
+        timestamp_ms % 1000
+    )
+}
+
+fn sample_value(sample: &Sample, column: &str, unit: Unit) -> Value {
+    let info = &sample.info;
+    let memory = |mb: Option<u64>| mb.map_or(Value::Null, |mb| unit.convert(mb));
+    match column {
+        "time" => Value::from(format_time(sample.timestamp_ms)),
+        "timestamp_ms" => Value::from(sample.timestamp_ms),
+        "total" => memory(Some(info.total_mb)),
+        "available" => memory(Some(info.available_mb)),
+        "used" => memory(Some(info.used_mb)),
+        "cache" => memory(Some(info.cache_mb)),
+        "usage_percent" => Value::from(info.usage_percent as f64),
+        "page_cache" => memory(Some(info.page_cache_mb)),
+        "buffers" => memory(info.buffers_mb),
+        "reclaimable_slab" => memory(info.reclaimable_slab_mb),
+        "shared" => memory(info.shared_mb),
+        "standby" => memory(info.standby_mb),
+        "modified" => memory(info.modified_mb),
+        _ => Value::Null,
+    }
+}
+
+fn clean_value(clean: &CleanMarker, column: &str, unit: Unit) -> Value {
+    match column {
+        "time" => Value::from(format_time(clean.timestamp_ms)),
+        "timestamp_ms" => Value::from(clean.timestamp_ms),
+        "cleaned" => unit.convert(clean.cleaned_mb),
+        "automatic" => Value::from(clean.automatic),
+        "duration_ms" => Value::from(clean.duration_ms),
+        _ => Value::Null,
+    }
+}
+
+/// Output name of a column: memory columns carry the unit.
+fn header(column: &str, unit: Unit) -> String {
+    if MEMORY_COLUMNS.contains(&column) {
+        format!("{}_{}", column, unit.suffix())
+    } else {
+        column.to_string()
+    }
+}
+
+/// The selected columns of `all`, keeping `all`'s order.
+fn select<'a>(all: &[&'a str], selected: &Option<Vec<String>>) -> Vec<&'a str> {
+    all.iter()
+        .copied()
+        .filter(|column| match selected {
+            Some(selected) => selected.iter().any(|s| s == column),
+            None => true,
+        })
+        .collect()
+}
+
+/// One record as a JSON object, starting with `"type": kind` when `kind`
+/// is given.
+fn json_object(
+    kind: Option<&str>,
+    columns: &[&str],
+    unit: Unit,
+    value: impl Fn(&str) -> Value,
+) -> Value {
+    let mut object = Map::new();
+    if let Some(kind) = kind {
+        object.insert("type".to_string(), Value::from(kind));
+    }
+    for column in columns {
+        object.insert(header(column, unit), value(column));
+    }
+    Value::Object(object)
+}
+
+/// Render `range` in the requested format.
+pub fn export(range: &HistoryRange, options: &ExportOptions) -> Result<String> {
+    let unit = options.unit;
+    let with_samples = options.dataset != Dataset::Cleans;
+    let with_cleans = options.dataset != Dataset::Samples;
+
+    if let Some(columns) = &options.columns {
+        let known = |c: &String| {
+            (with_samples && SAMPLE_COLUMNS.contains(&c.as_str()))
+                || (with_cleans && CLEAN_COLUMNS.contains(&c.as_str()))
+        };
+        if let Some(unknown) = columns.iter().find(|c| !known(c)) {
+            let mut valid: Vec<&str> = Vec::new();
+            if with_samples {
+                valid.extend(SAMPLE_COLUMNS);
+            }
+            if with_cleans {
+                for column in CLEAN_COLUMNS {
+                    if !valid.contains(column) {
+                        valid.push(column);
+                    }
+                }
+            }
+            return Err(Error::invalid_input(format!(
+                "Unknown column: {} (expected {})",
+                unknown,
+                valid.join(", ")
+            )));
+        }
+    }
+    let sample_columns = select(SAMPLE_COLUMNS, &options.columns);
+    let clean_columns = select(CLEAN_COLUMNS, &options.columns);
+
+    // With both datasets as JSON Lines, tag each line so they can be told
+    // apart
+    let tagged = options.format == ExportFormat::JsonLines && options.dataset == Dataset::All;
+    let samples = || {
+        range.samples.iter().map(|s| {
+            let kind = tagged.then_some("sample");
+            json_object(kind, &sample_columns, unit, |c| sample_value(s, c, unit))
+        })
+    };
+    let cleans = || {
+        range.cleans.iter().map(|m| {
+            let kind = tagged.then_some("clean");
+            json_object(kind, &clean_columns, unit, |c| clean_value(m, c, unit))
+        })
+    };
+
+    let mut out = String::new();
+    match options.format {
+        ExportFormat::Csv => {
+            let (columns, rows): (&[&str], Vec<Vec<Value>>) = match options.dataset {
+                Dataset::Samples => (
+                    &sample_columns,
+                    range
+                        .samples
+                        .iter()
+                        .map(|s| {
+                            sample_columns
+                                .iter()
+                                .map(|c| sample_value(s, c, unit))
+                                .collect()
+                        })
+                        .collect(),
+                ),
+                Dataset::Cleans => (
+                    &clean_columns,
+                    range
+                        .cleans
+                        .iter()
+                        .map(|m| {
+                            clean_columns
+                                .iter()
+                                .map(|c| clean_value(m, c, unit))
+                                .collect()
+                        })
+                        .collect(),
+                ),
+                Dataset::All => {
+                    return Err(Error::invalid_input(
+                        "CSV holds one table; export samples or cleans",
+                    ))
+                }
+            };
+            let headers: Vec<String> = columns.iter().map(|c| header(c, unit)).collect();
+            let _ = writeln!(out, "{}", headers.join(","));
+            for row in rows {
+                let cells: Vec<String> = row.iter().map(csv_cell).collect();
+                let _ = writeln!(out, "{}", cells.join(","));
+            }
+        }
+        ExportFormat::JsonLines => {
+            if with_samples {
+                for object in samples() {
+                    let _ = writeln!(out, "{}", object);
+                }
+            }
+            if with_cleans {
+                for object in cleans() {
+                    let _ = writeln!(out, "{}", object);
+                }
+            }
+        }
+        ExportFormat::Json => {
+            let samples = Value::Array(samples().collect());
+            let cleans = Value::Array(cleans().collect());
+            let document = match options.dataset {
+                Dataset::Samples => samples,
+                Dataset::Cleans => cleans,
+                Dataset::All => {
+                    let mut both = Map::new();
+                    both.insert("samples".to_string(), samples);
+                    both.insert("cleans".to_string(), cleans);
+                    Value::Object(both)
+                }
+            };
+            let _ = writeln!(out, "{}", document);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MemoryInfo;
+
+    fn range() -> HistoryRange {
+        let info = MemoryInfo {
+            total_mb: 8192,
+            available_mb: 4096,
+            used_mb: 4096,
+            cache_mb: 2048,
+            usage_percent: 50.0,
+            page_cache_mb: 1536,
+            buffers_mb: Some(64),
+            reclaimable_slab_mb: None,
+            shared_mb: None,
+            standby_mb: None,
+            modified_mb: None,
+        };
+        HistoryRange {
+            resolution_ms: 1000,
+            samples: vec![Sample {
+                timestamp_ms: 1_700_000_000_000,
+                info,
+            }],
+            cleans: vec![CleanMarker {
+                timestamp_ms: 1_700_000_001_500,
+                cleaned_mb: 512,
+                automatic: true,
+                duration_ms: 40,
+            }],
+        }
+    }
+
+    #[test]
+    fn formats_utc_time() {
+        assert_eq!(format_time(0), "1970-01-01T00:00:00.000Z");
+        assert_eq!(format_time(1_700_000_001_500), "2023-11-14T22:13:21.500Z");
+    }
+
+    #[test]
+    fn csv_uses_unit_suffixes_and_blank_nulls() {
+        let options = ExportOptions {
+            columns: Some(vec![
+                "timestamp_ms".into(),
+                "cache".into(),
+                "standby".into(),
+            ]),
+            unit: Unit::Kb,
+            ..ExportOptions::default()
+        };
+        let out = export(&range(), &options).unwrap();
+        assert_eq!(
+            out,
+            "timestamp_ms,cache_kb,standby_kb\n1700000000000,2097152,\n"
+        );
+    }
+
+    #[test]
+    fn tagged_json_lines_stay_valid_without_columns() {
+        let options = ExportOptions {
+            format: ExportFormat::JsonLines,
+            dataset: Dataset::All,
+            columns: Some(vec!["cleaned".into()]),
+            ..ExportOptions::default()
+        };
+        let out = export(&range(), &options).unwrap();
+        let lines: Vec<Value> = out
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines[0], serde_json::json!({ "type": "sample" }));
+        assert_eq!(
+            lines[1],
+            serde_json::json!({ "type": "clean", "cleaned_mb": 512 })
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_columns_and_csv_of_both() {
+        let options = ExportOptions {
+            columns: Some(vec!["bogus".into()]),
+            ..ExportOptions::default()
+        };
+        assert!(export(&range(), &options).is_err());
+        let options = ExportOptions {
+            dataset: Dataset::All,
+            ..ExportOptions::default()
+        };
+        assert!(export(&range(), &options).is_err());
+    }
+}
@@ -0,0 +1,387 @@
+// Prometheus text-format exporter served on localhost
+
+use crate::error::{Error, Result};
+use crate::history::{CleanStats, DURATION_BUCKETS};
+use crate::{Config, MemoryInfo};
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+pub const DEFAULT_METRICS_PORT: u16 = 9187;
+
+const MB: u64 = 1024 * 1024;
+
+/// Quote a label value per the text format.
+fn label(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Render the exposition text. `info` is `None` when the memory source
+/// could not be read, which is reported through `mcm_memory_source_up`.
+pub fn render(info: Option<&MemoryInfo>, config: &Config, stats: &CleanStats) -> String {
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "mcm_memory_source_up",
+        "gauge",
+        "Whether the last memory reading succeeded.",
+    );
+    let _ = writeln!(out, "mcm_memory_source_up {}", info.is_some() as u8);
+
+    if let Some(info) = info {
+        let gauges = [
+            ("total", "Physical memory.", Some(info.total_mb)),
+            (
+                "available",
+                "Memory available without swapping.",
+                Some(info.available_mb),
+            ),
+            ("used", "Memory in use.", Some(info.used_mb)),
+            ("cache", "Reclaimable cache.", Some(info.cache_mb)),
+            (
+                "page_cache",
+                "File-backed page cache.",
+                Some(info.page_cache_mb),
+            ),
+            ("buffers", "Block device buffers.", info.buffers_mb),
+            (
+                "reclaimable_slab",
+                "Reclaimable kernel slab.",
+                info.reclaimable_slab_mb,
+            ),
+            ("shared", "Shared memory and tmpfs.", info.shared_mb),
+            ("standby", "Windows standby list.", info.standby_mb),
+            ("modified", "Windows modified list.", info.modified_mb),
+        ];
+        for (name, help, value) in gauges {
+            if let Some(mb) = value {
+                let metric = format!("mcm_memory_{}_bytes", name);
+                header(&mut out, &metric, "gauge", help);
+                let _ = writeln!(out, "{} {}", metric, mb * MB);
+            }
+        }
+        header(
+            &mut out,
+            "mcm_memory_usage_ratio",
+            "gauge",
+            "Used memory as a fraction of total.",
+        );
+        let _ = writeln!(out, "mcm_memory_usage_ratio {}", info.usage_percent / 100.0);
+
+        let (start_mb, stop_mb) = config.thresholds_mb(info.total_mb);
+        header(
+            &mut out,
+            "mcm_threshold_bytes",
+            "gauge",
+            "Configured thresholds resolved to bytes.",
+        );
+        let _ = writeln!(
+            out,
+            "mcm_threshold_bytes{{threshold=\"start\"}} {}",
+            start_mb * MB
+        );
+        let _ = writeln!(
+            out,
+            "mcm_threshold_bytes{{threshold=\"stop\"}} {}",
+            stop_mb * MB
+        );
+    }
+
+    header(
+        &mut out,
+        "mcm_config_info",
+        "gauge",
+        "Current configuration.",
+    );
+    let _ = writeln!(
+        out,
+        "mcm_config_info{{threshold_mode={},start_threshold_mb=\"{}\",stop_threshold_mb=\"{}\",start_threshold_percent=\"{}\",stop_threshold_percent=\"{}\",auto_clean_enabled=\"{}\",strategy={}}} 1",
+        label(&config.threshold_mode.to_string()),
+        config.start_threshold_mb,
+        config.stop_threshold_mb,
+        config.start_threshold_percent,
+        config.stop_threshold_percent,
+        config.auto_clean_enabled,
+        label(&config.strategy.to_string()),
+    );
+
+    header(
+        &mut out,
+        "mcm_cleans_total",
+        "counter",
+        "Completed cleans since startup.",
+    );
+    let _ = writeln!(
+        out,
+        "mcm_cleans_total{{trigger=\"automatic\"}} {}",
+        stats.automatic
+    );
+    let _ = writeln!(
+        out,
+        "mcm_cleans_total{{trigger=\"manual\"}} {}",
+        stats.manual
+    );
+
+    header(
+        &mut out,
+        "mcm_cleaned_bytes_total",
+        "counter",
+        "Cache freed by cleans since startup.",
+    );
+    let _ = writeln!(out, "mcm_cleaned_bytes_total {}", stats.cleaned_mb * MB);
+
+    header(
+        &mut out,
+        "mcm_clean_duration_seconds",
+        "histogram",
+        "Time taken by each clean.",
+    );
+    for (i, bound) in DURATION_BUCKETS.iter().enumerate() {
+        let count = stats.duration_buckets.get(i).copied().unwrap_or(0);
+        let _ = writeln!(
+            out,
+            "mcm_clean_duration_seconds_bucket{{le=\"{}\"}} {}",
+            bound, count
+        );
+    }
+    let _ = writeln!(
+        out,
+        "mcm_clean_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+        stats.count()
+    );
+    let _ = writeln!(
+        out,
+        "mcm_clean_duration_seconds_sum {}",
+        stats.duration_sum_secs
+    );
+    let _ = writeln!(out, "mcm_clean_duration_seconds_count {}", stats.count());
+
+    out
+}
+
+/// Serves `GET /metrics` on 127.0.0.1 until stopped or dropped. Each
+/// scrape calls `render` for a fresh body; requests that fail are passed
+/// to `on_error`.
+pub struct MetricsServer {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub fn start(
+        port: u16,
+        render: impl Fn() -> String + Send + 'static,
+        mut on_error: impl FnMut(Error) + Send + 'static,
+    ) -> Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+            .map_err(|e| Error::io(format!("Failed to listen on 127.0.0.1:{}", port), e))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| Error::io("Failed to read the metrics address", e))?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let thread_stopped = Arc::clone(&stopped);
+
+        let handle = std::thread::Builder::new()
+            .name("metrics".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if thread_stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        if let Err(e) = serve(stream, &render) {
+                            on_error(Error::io("Metrics request failed", e));
+                        }
+                    }
+                }
+            })
+            .map_err(|e| Error::io("Failed to start the metrics server", e))?;
+
+        Ok(Self {
+            addr,
+            stopped,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn stop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the blocking accept so the thread sees the flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn serve(stream: TcpStream, render: &dyn Fn() -> String) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain headers so the client is not reset mid-send
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            render(),
+        ),
+        (Some("GET"), _) => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method not allowed\n".to_string(),
+        ),
+    };
+
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clean::Strategy;
+    use crate::memory::tests::memory;
+
+    fn config() -> Config {
+        Config {
+            start_threshold_mb: 2000,
+            stop_threshold_mb: 1000,
+            start_threshold_percent: 80.0,
+            stop_threshold_percent: 60.5,
+            auto_clean_enabled: true,
+            strategy: Strategy::parse("drop-caches:3:no-sync").unwrap(),
+            ..Config::default()
+        }
+    }
+
+    fn stats() -> CleanStats {
+        let mut stats = CleanStats::default();
+        stats.record(true, 100, Duration::from_millis(250));
+        stats.record(false, 200, Duration::from_secs(2));
+        stats.record(true, 300, Duration::from_secs(45));
+        stats
+    }
+
+    fn lines(text: &str) -> Vec<&str> {
+        text.lines().filter(|line| !line.starts_with('#')).collect()
+    }
+
+    #[test]
+    fn renders_memory_gauges() {
+        let mut info = memory(8000, 4000, 1500);
+        info.buffers_mb = Some(100);
+        let text = render(Some(&info), &config(), &stats());
+        let lines = lines(&text);
+
+        for line in [
+            "mcm_memory_source_up 1",
+            "mcm_memory_total_bytes 8388608000",
+            "mcm_memory_available_bytes 4194304000",
+            "mcm_memory_cache_bytes 1572864000",
+            "mcm_memory_buffers_bytes 104857600",
+            "mcm_memory_usage_ratio 0.5",
+            "mcm_threshold_bytes{threshold=\"start\"} 2097152000",
+            "mcm_threshold_bytes{threshold=\"stop\"} 1048576000",
+        ] {
+            assert!(lines.contains(&line), "missing {}", line);
+        }
+        assert!(text.contains("# TYPE mcm_memory_total_bytes gauge\n"));
+        // Categories the platform does not have are left out
+        assert!(!text.contains("mcm_memory_standby_bytes"));
+    }
+
+    #[test]
+    fn renders_clean_counters_and_histogram() {
+        let text = render(None, &config(), &stats());
+        let lines = lines(&text);
+        let histogram: Vec<&str> = lines
+            .iter()
+            .copied()
+            .filter(|line| line.starts_with("mcm_clean_duration_seconds"))
+            .collect();
+        assert_eq!(
+            histogram,
+            vec![
+                "mcm_clean_duration_seconds_bucket{le=\"0.1\"} 0",
+                "mcm_clean_duration_seconds_bucket{le=\"0.5\"} 1",
+                "mcm_clean_duration_seconds_bucket{le=\"1\"} 1",
+                "mcm_clean_duration_seconds_bucket{le=\"2.5\"} 2",
+                "mcm_clean_duration_seconds_bucket{le=\"5\"} 2",
+                "mcm_clean_duration_seconds_bucket{le=\"10\"} 2",
+                "mcm_clean_duration_seconds_bucket{le=\"30\"} 2",
+                "mcm_clean_duration_seconds_bucket{le=\"60\"} 3",
+                "mcm_clean_duration_seconds_bucket{le=\"+Inf\"} 3",
+                "mcm_clean_duration_seconds_sum 47.25",
+                "mcm_clean_duration_seconds_count 3",
+            ]
+        );
+        assert!(text.contains("# TYPE mcm_clean_duration_seconds histogram\n"));
+        assert!(lines.contains(&"mcm_cleans_total{trigger=\"automatic\"} 2"));
+        assert!(lines.contains(&"mcm_cleans_total{trigger=\"manual\"} 1"));
+        assert!(lines.contains(&"mcm_cleaned_bytes_total 629145600"));
+    }
+
+    #[test]
+    fn renders_config_labels() {
+        let text = render(None, &config(), &CleanStats::default());
+        assert!(lines(&text).contains(
+            &"mcm_config_info{threshold_mode=\"absolute_mb\",start_threshold_mb=\"2000\",stop_threshold_mb=\"1000\",start_threshold_percent=\"80\",stop_threshold_percent=\"60.5\",auto_clean_enabled=\"true\",strategy=\"drop-caches:3:no-sync\"} 1"
+        ), "{}", text);
+    }
+
+    #[test]
+    fn reports_an_unreadable_source() {
+        let text = render(None, &config(), &CleanStats::default());
+        assert!(lines(&text).contains(&"mcm_memory_source_up 0"));
+        assert!(!text.contains("mcm_memory_total_bytes"));
+        assert!(!text.contains("mcm_threshold_bytes"));
+        // No cleans yet: empty buckets still render
+        assert!(text.contains("mcm_clean_duration_seconds_bucket{le=\"0.1\"} 0\n"));
+    }
+
+    #[test]
+    fn quotes_label_values() {
+        assert_eq!(label("a\"b\\c\nd"), "\"a\\\"b\\\\c\\nd\"");
+    }
+}
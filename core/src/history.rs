@@ -0,0 +1,478 @@
+// Bounded in-memory time series of MemoryInfo samples and cleans
+
+use crate::error::Result;
+use crate::metrics_store::MetricsStore;
+use crate::MemoryInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Milliseconds since the Unix epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub timestamp_ms: u64,
+    pub info: MemoryInfo,
+}
+
+/// A clean, for lining up against the samples around it.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CleanMarker {
+    pub timestamp_ms: u64,
+    pub cleaned_mb: u64,
+    /// Started by the scheduler rather than by hand
+    pub automatic: bool,
+    #[serde(default)]
+    pub duration_ms: u64,
+}
+
+/// Upper bounds of the clean duration histogram, in seconds.
+pub const DURATION_BUCKETS: &[f64] = &[0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Running clean totals since startup.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CleanStats {
+    pub automatic: u64,
+    pub manual: u64,
+    pub cleaned_mb: u64,
+    /// Cumulative count per `DURATION_BUCKETS` entry
+    pub duration_buckets: Vec<u64>,
+    pub duration_sum_secs: f64,
+}
+
+impl CleanStats {
+    pub fn record(&mut self, automatic: bool, cleaned_mb: u64, duration: Duration) {
+        if automatic {
+            self.automatic += 1;
+        } else {
+            self.manual += 1;
+        }
+        self.cleaned_mb += cleaned_mb;
+
+        let secs = duration.as_secs_f64();
+        self.duration_sum_secs += secs;
+        self.duration_buckets.resize(DURATION_BUCKETS.len(), 0);
+        for (count, &bound) in self.duration_buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if secs <= bound {
+                *count += 1;
+            }
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.automatic + self.manual
+    }
+}
+
+/// Samples and cleans in a time range, as returned by `History::query`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub struct HistoryRange {
+    pub resolution_ms: u64,
+    pub samples: Vec<Sample>,
+    pub cleans: Vec<CleanMarker>,
+}
+
+/// Two tiers: recent samples at full resolution, then averages over
+/// `coarse_resolution` for older ones.
+#[derive(Debug, Clone)]
+pub struct HistoryOptions {
+    /// Minimum spacing between stored samples; closer ones are dropped.
+    pub resolution: Duration,
+    /// How long full-resolution samples are kept.
+    pub retention: Duration,
+    /// Bucket size samples are averaged into once they age out.
+    pub coarse_resolution: Duration,
+    /// How long averaged samples and clean markers are kept.
+    pub coarse_retention: Duration,
+}
+
+impl Default for HistoryOptions {
+    fn default() -> Self {
+        Self {
+            resolution: Duration::from_secs(3),
+            retention: Duration::from_secs(60 * 60),
+            coarse_resolution: Duration::from_secs(60),
+            coarse_retention: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+/// How often the on-disk store is compacted and pruned.
+const STORE_MAINTENANCE_INTERVAL_MS: u64 = 10 * 60 * 1000;
+
+#[derive(Debug)]
+pub struct History {
+    options: HistoryOptions,
+    raw: VecDeque<Sample>,
+    coarse: VecDeque<Sample>,
+    /// Aged-out samples waiting for their coarse bucket to fill
+    pending: Vec<Sample>,
+    cleans: VecDeque<CleanMarker>,
+    /// Persists everything recorded and serves queries older than memory
+    store: Option<MetricsStore>,
+    last_maintained_ms: u64,
+    /// Totals over every clean recorded, unaffected by retention
+    stats: CleanStats,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(HistoryOptions::default())
+    }
+}
+
+fn ms(duration: Duration) -> u64 {
+    (duration.as_millis() as u64).max(1)
+}
+
+/// Average of `samples`, stamped with the first one's time. Optional fields
+/// are averaged over the samples that have them.
+fn average(samples: &[Sample]) -> Sample {
+    let n = samples.len().max(1) as u64;
+    let mean =
+        |field: fn(&MemoryInfo) -> u64| samples.iter().map(|s| field(&s.info)).sum::<u64>() / n;
+    let mean_opt = |field: fn(&MemoryInfo) -> Option<u64>| {
+        let values: Vec<u64> = samples.iter().filter_map(|s| field(&s.info)).collect();
+        (!values.is_empty()).then(|| values.iter().sum::<u64>() / values.len() as u64)
+    };
+
+    Sample {
+        timestamp_ms: samples.first().map(|s| s.timestamp_ms).unwrap_or(0),
+        info: MemoryInfo {
+            total_mb: mean(|i| i.total_mb),
+            available_mb: mean(|i| i.available_mb),
+            used_mb: mean(|i| i.used_mb),
+            cache_mb: mean(|i| i.cache_mb),
+            usage_percent: samples.iter().map(|s| s.info.usage_percent).sum::<f32>() / n as f32,
+            page_cache_mb: mean(|i| i.page_cache_mb),
+            buffers_mb: mean_opt(|i| i.buffers_mb),
+            reclaimable_slab_mb: mean_opt(|i| i.reclaimable_slab_mb),
+            shared_mb: mean_opt(|i| i.shared_mb),
+            standby_mb: mean_opt(|i| i.standby_mb),
+            modified_mb: mean_opt(|i| i.modified_mb),
+        },
+    }
+}
+
+/// Average consecutive samples that fall in the same `resolution_ms` bucket.
+pub fn downsample(samples: &[Sample], resolution_ms: u64) -> Vec<Sample> {
+    let resolution_ms = resolution_ms.max(1);
+    let mut buckets = Vec::new();
+    let mut start = 0;
+    for i in 1..=samples.len() {
+        let bucket_ends = i == samples.len()
+            || samples[i].timestamp_ms / resolution_ms
+                != samples[start].timestamp_ms / resolution_ms;
+        if bucket_ends {
+            buckets.push(average(&samples[start..i]));
+            start = i;
+        }
+    }
+    buckets
+}
+
+impl History {
+    pub fn new(options: HistoryOptions) -> Self {
+        Self {
+            options,
+            raw: VecDeque::new(),
+            coarse: VecDeque::new(),
+            pending: Vec::new(),
+            cleans: VecDeque::new(),
+            store: None,
+            last_maintained_ms: 0,
+            stats: CleanStats::default(),
+        }
+    }
+
+    /// Also write every sample and clean to `store`, and read ranges older
+    /// than what is held in memory back from it.
+    pub fn with_store(options: HistoryOptions, store: MetricsStore) -> Self {
+        Self {
+            store: Some(store),
+            ..Self::new(options)
+        }
+    }
+
+    pub fn options(&self) -> &HistoryOptions {
+        &self.options
+    }
+
+    pub fn clean_stats(&self) -> &CleanStats {
+        &self.stats
+    }
+
+    pub fn record(&mut self, info: MemoryInfo) -> Result<()> {
+        self.record_at(now_ms(), info)
+    }
+
+    /// Add a sample taken at `timestamp_ms`. Samples closer than
+    /// `resolution` to the previous one, or older than it, are dropped.
+    /// The sample is kept in memory even if the store fails; the store's
+    /// error is returned.
+    pub fn record_at(&mut self, timestamp_ms: u64, info: MemoryInfo) -> Result<()> {
+        if let Some(last) = self.raw.back() {
+            if timestamp_ms < last.timestamp_ms + ms(self.options.resolution) {
+                return Ok(());
+            }
+        }
+        let sample = Sample { timestamp_ms, info };
+        let stored = match &mut self.store {
+            Some(store) => store.append_sample(&sample),
+            None => Ok(()),
+        };
+        self.raw.push_back(sample);
+        let maintained = self.expire(timestamp_ms);
+        stored.and(maintained)
+    }
+
+    /// Add a clean, kept in memory and counted even if the store fails.
+    pub fn record_clean(&mut self, marker: CleanMarker) -> Result<()> {
+        let now = marker.timestamp_ms;
+        self.stats.record(
+            marker.automatic,
+            marker.cleaned_mb,
+            Duration::from_millis(marker.duration_ms),
+        );
+        let stored = match &mut self.store {
+            Some(store) => store.append_clean(&marker),
+            None => Ok(()),
+        };
+        self.cleans.push_back(marker);
+        let maintained = self.expire(now);
+        stored.and(maintained)
+    }
+
+    /// Move aged-out samples into the coarse tier and drop what is past
+    /// `coarse_retention`. Fails only if store maintenance does.
+    fn expire(&mut self, now: u64) -> Result<()> {
+        let raw_cutoff = now.saturating_sub(ms(self.options.retention));
+        let coarse_cutoff = now.saturating_sub(ms(self.options.coarse_retention));
+        let bucket_ms = ms(self.options.coarse_resolution);
+
+        while let Some(sample) = self.raw.front() {
+            if sample.timestamp_ms >= raw_cutoff {
+                break;
+            }
+            let bucket = sample.timestamp_ms / bucket_ms;
+            if let Some(first) = self.pending.first() {
+                if first.timestamp_ms / bucket_ms != bucket {
+                    self.coarse.push_back(average(&self.pending));
+                    self.pending.clear();
+                }
+            }
+            self.pending.extend(self.raw.pop_front());
+        }
+
+        while self
+            .coarse
+            .front()
+            .is_some_and(|s| s.timestamp_ms < coarse_cutoff)
+        {
+            self.coarse.pop_front();
+        }
+        // Only after a long gap in samples is the open bucket this old
+        if self
+            .pending
+            .first()
+            .is_some_and(|s| s.timestamp_ms < coarse_cutoff)
+        {
+            self.pending.clear();
+        }
+        while self
+            .cleans
+            .front()
+            .is_some_and(|c| c.timestamp_ms < coarse_cutoff)
+        {
+            self.cleans.pop_front();
+        }
+
+        match &mut self.store {
+            Some(store) if now >= self.last_maintained_ms + STORE_MAINTENANCE_INTERVAL_MS => {
+                self.last_maintained_ms = now;
+                store.maintain(now)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Timestamp of the oldest sample held in memory.
+    fn oldest_ms(&self) -> Option<u64> {
+        self.coarse
+            .front()
+            .or(self.pending.first())
+            .or(self.raw.front())
+            .map(|s| s.timestamp_ms)
+    }
+
+    /// Samples and cleans with `from_ms <= timestamp < to_ms`, averaged into
+    /// `resolution_ms` buckets (never finer than what is stored). The part
+    /// of the range older than memory comes from the store, if any, and
+    /// fails if the store cannot be read.
+    pub fn query(&self, from_ms: u64, to_ms: u64, resolution_ms: u64) -> Result<HistoryRange> {
+        let memory_from = self.oldest_ms().unwrap_or(to_ms).max(from_ms);
+        let (mut samples, mut cleans) = match &self.store {
+            Some(store) if from_ms < memory_from => {
+                store.read_range(from_ms, memory_from.min(to_ms))?
+            }
+            _ => Default::default(),
+        };
+
+        let in_memory = |timestamp_ms: u64| timestamp_ms >= memory_from && timestamp_ms < to_ms;
+        samples.extend(
+            self.coarse
+                .iter()
+                .chain(&self.pending)
+                .chain(&self.raw)
+                .filter(|s| in_memory(s.timestamp_ms))
+                .cloned(),
+        );
+        cleans.extend(
+            self.cleans
+                .iter()
+                .filter(|c| in_memory(c.timestamp_ms))
+                .cloned(),
+        );
+        let resolution_ms = resolution_ms.max(ms(self.options.resolution));
+
+        Ok(HistoryRange {
+            resolution_ms,
+            samples: downsample(&samples, resolution_ms),
+            cleans,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.coarse.len() + self.pending.len() + self.raw.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::tests::memory;
+
+    /// 1 s samples kept for 10 s, then 5 s averages kept for a minute.
+    fn history() -> History {
+        History::new(HistoryOptions {
+            resolution: Duration::from_secs(1),
+            retention: Duration::from_secs(10),
+            coarse_resolution: Duration::from_secs(5),
+            coarse_retention: Duration::from_secs(60),
+        })
+    }
+
+    /// One sample a second from 0 to 30 s, with cache MB equal to the
+    /// second it was taken in.
+    fn filled() -> History {
+        let mut history = history();
+        for second in 0..=30 {
+            history
+                .record_at(second * 1000, memory(8000, 4000, second))
+                .unwrap();
+        }
+        history
+    }
+
+    fn caches(range: &HistoryRange) -> Vec<u64> {
+        range.samples.iter().map(|s| s.info.cache_mb).collect()
+    }
+
+    #[test]
+    fn drops_samples_closer_than_the_resolution() {
+        let mut history = history();
+        history.record_at(1000, memory(8000, 4000, 1)).unwrap();
+        history.record_at(1500, memory(8000, 4000, 2)).unwrap();
+        history.record_at(1999, memory(8000, 4000, 3)).unwrap();
+        history.record_at(2000, memory(8000, 4000, 4)).unwrap();
+        history.record_at(1800, memory(8000, 4000, 5)).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(caches(&history.query(0, 10_000, 0).unwrap()), vec![1, 4]);
+    }
+
+    #[test]
+    fn averages_aged_out_samples_per_coarse_bucket() {
+        let history = filled();
+        // Seconds 0-14 averaged into three buckets, 15-19 waiting for their
+        // bucket to close, 20-30 still at full resolution
+        assert_eq!(history.coarse.len(), 3);
+        assert_eq!(history.pending.len(), 5);
+        assert_eq!(history.raw.len(), 11);
+        let coarse: Vec<(u64, u64)> = history
+            .coarse
+            .iter()
+            .map(|s| (s.timestamp_ms, s.info.cache_mb))
+            .collect();
+        assert_eq!(coarse, vec![(0, 2), (5000, 7), (10_000, 12)]);
+    }
+
+    #[test]
+    fn downsample_averages_within_buckets() {
+        let samples: Vec<Sample> = [0, 400, 900, 1000, 2500]
+            .iter()
+            .zip([10, 20, 30, 40, 50])
+            .map(|(&timestamp_ms, cache_mb)| Sample {
+                timestamp_ms,
+                info: memory(8000, 4000, cache_mb),
+            })
+            .collect();
+        let buckets = downsample(&samples, 1000);
+        let buckets: Vec<(u64, u64)> = buckets
+            .iter()
+            .map(|s| (s.timestamp_ms, s.info.cache_mb))
+            .collect();
+        assert_eq!(buckets, vec![(0, 20), (1000, 40), (2500, 50)]);
+        assert!(downsample(&[], 1000).is_empty());
+    }
+
+    #[test]
+    fn query_spans_coarse_and_raw_samples() {
+        let history = filled();
+        let range = history.query(0, 31_000, 0).unwrap();
+        assert_eq!(range.resolution_ms, 1000);
+        assert_eq!(range.samples.len(), 19);
+        assert_eq!(range.samples.first().unwrap().info.cache_mb, 2);
+        assert_eq!(range.samples.last().unwrap().timestamp_ms, 30_000);
+
+        let range = history.query(0, 31_000, 5000).unwrap();
+        assert_eq!(caches(&range), vec![2, 7, 12, 17, 22, 27, 30]);
+
+        // Only the raw part
+        assert_eq!(
+            caches(&history.query(25_000, 28_000, 0).unwrap()),
+            vec![25, 26, 27]
+        );
+    }
+
+    #[test]
+    fn expires_everything_past_the_coarse_retention() {
+        let mut history = filled();
+        history
+            .record_clean(CleanMarker {
+                timestamp_ms: 30_000,
+                cleaned_mb: 100,
+                automatic: true,
+                duration_ms: 1500,
+            })
+            .unwrap();
+        assert_eq!(history.query(0, 31_000, 0).unwrap().cleans.len(), 1);
+
+        history.record_at(100_000, memory(8000, 4000, 100)).unwrap();
+        assert_eq!(history.len(), 1);
+        let range = history.query(0, 101_000, 0).unwrap();
+        assert_eq!(caches(&range), vec![100]);
+        assert!(range.cleans.is_empty());
+        // Totals outlive retention
+        assert_eq!(history.clean_stats().count(), 1);
+    }
+}
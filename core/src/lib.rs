@@ -0,0 +1,24 @@
+//! Memory readings, cleaning strategies and configuration shared by the
+//! Memory Cache Manager frontends. Has no Tauri or webview dependency.
+
+pub mod clean;
+pub mod config;
+pub mod error;
+pub mod export;
+pub mod exporter;
+pub mod history;
+pub mod memory;
+pub mod metrics_store;
+pub mod monitor;
+pub mod process;
+pub mod scheduler;
+pub mod source;
+pub mod threshold;
+
+pub use config::Config;
+pub use error::{Error, Result};
+pub use history::History;
+pub use memory::MemoryInfo;
+pub use scheduler::Scheduler;
+pub use source::MemorySource;
+pub use threshold::ThresholdMode;
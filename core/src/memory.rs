@@ -0,0 +1,46 @@
+// Memory snapshot type shared by every source
+
+use serde::{Deserialize, Serialize};
+
+/// Memory snapshot. `cache_mb` is the measured reclaimable cache; the
+/// breakdown fields are `None` where the platform has no such category.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MemoryInfo {
+    pub total_mb: u64,
+    pub available_mb: u64,
+    pub used_mb: u64,
+    pub cache_mb: u64,
+    pub usage_percent: f32,
+    /// File-backed page cache (Linux: Cached minus Shmem; Windows: system
+    /// cache working set)
+    pub page_cache_mb: u64,
+    pub buffers_mb: Option<u64>,
+    pub reclaimable_slab_mb: Option<u64>,
+    /// Shared memory and tmpfs, which cannot be dropped like cache
+    pub shared_mb: Option<u64>,
+    pub standby_mb: Option<u64>,
+    pub modified_mb: Option<u64>,
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A reading with used memory and usage derived from `available_mb`.
+    pub(crate) fn memory(total_mb: u64, available_mb: u64, cache_mb: u64) -> MemoryInfo {
+        let used_mb = total_mb - available_mb;
+        MemoryInfo {
+            total_mb,
+            available_mb,
+            used_mb,
+            cache_mb,
+            usage_percent: used_mb as f32 / total_mb as f32 * 100.0,
+            page_cache_mb: cache_mb,
+            buffers_mb: None,
+            reclaimable_slab_mb: None,
+            shared_mb: None,
+            standby_mb: None,
+            modified_mb: None,
+        }
+    }
+}
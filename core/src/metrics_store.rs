@@ -0,0 +1,446 @@
+// On-disk store of samples and cleans in append-only segment files
+
+use crate::config::config_dir;
+use crate::error::{Error, Result};
+use crate::history::{downsample, CleanMarker, Sample};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const METRICS_DIR_NAME: &str = "metrics";
+
+const SEGMENT_EXT: &str = ".jsonl";
+const COMPACT_EXT: &str = ".compact.jsonl";
+
+/// One line of a segment file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Record {
+    Sample(Sample),
+    Clean(CleanMarker),
+}
+
+impl Record {
+    fn timestamp_ms(&self) -> u64 {
+        match self {
+            Record::Sample(sample) => sample.timestamp_ms,
+            Record::Clean(clean) => clean.timestamp_ms,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StoreOptions {
+    /// Time covered by each segment file.
+    pub segment_duration: Duration,
+    /// Segments older than this are deleted.
+    pub retention: Duration,
+    /// Segments older than this are rewritten at `compact_resolution`.
+    pub compact_after: Duration,
+    pub compact_resolution: Duration,
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        Self {
+            segment_duration: Duration::from_secs(60 * 60),
+            retention: Duration::from_secs(7 * 24 * 60 * 60),
+            compact_after: Duration::from_secs(24 * 60 * 60),
+            compact_resolution: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A segment file on disk.
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    start_ms: u64,
+    compacted: bool,
+    path: PathBuf,
+}
+
+/// Samples and cleans persisted as JSON lines in one file per
+/// `segment_duration`, named after the segment's start time in ms. Records
+/// are only ever appended; old segments are compacted by rewriting them
+/// through a temp file and deleted once past `retention`.
+#[derive(Debug)]
+pub struct MetricsStore {
+    dir: PathBuf,
+    options: StoreOptions,
+    /// Segment start and handle currently appended to
+    current: Option<(u64, File)>,
+}
+
+fn ms(duration: Duration) -> u64 {
+    (duration.as_millis() as u64).max(1)
+}
+
+impl MetricsStore {
+    pub fn open(dir: impl Into<PathBuf>, options: StoreOptions) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .map_err(|e| Error::io(format!("Failed to create {}", dir.display()), e))?;
+        Ok(Self {
+            dir,
+            options,
+            current: None,
+        })
+    }
+
+    /// The `metrics` directory next to the config file.
+    pub fn default_location() -> Result<Self> {
+        let dir = config_dir()
+            .ok_or_else(|| Error::unsupported("Could not determine the config directory"))?;
+        Self::open(dir.join(METRICS_DIR_NAME), StoreOptions::default())
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn append_sample(&mut self, sample: &Sample) -> Result<()> {
+        self.append(&Record::Sample(sample.clone()))
+    }
+
+    pub fn append_clean(&mut self, clean: &CleanMarker) -> Result<()> {
+        self.append(&Record::Clean(clean.clone()))
+    }
+
+    fn append(&mut self, record: &Record) -> Result<()> {
+        let segment_ms = ms(self.options.segment_duration);
+        let start_ms = record.timestamp_ms() / segment_ms * segment_ms;
+        let mut line =
+            serde_json::to_vec(record).map_err(|e| Error::invalid_input(e.to_string()))?;
+        line.push(b'\n');
+
+        if self.current.as_ref().map(|(start, _)| *start) != Some(start_ms) {
+            let path = self.dir.join(format!("{}{}", start_ms, SEGMENT_EXT));
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| Error::io(format!("Failed to open {}", path.display()), e))?;
+            self.current = Some((start_ms, file));
+        }
+        let (_, file) = self.current.as_mut().expect("segment opened above");
+        // One write per line so a crash can at worst truncate the last one
+        file.write_all(&line)
+            .map_err(|e| Error::io(format!("Failed to append to {}", self.dir.display()), e))
+    }
+
+    fn segments(&self) -> Result<Vec<Segment>> {
+        let entries = fs::read_dir(&self.dir)
+            .map_err(|e| Error::io(format!("Failed to read {}", self.dir.display()), e))?;
+        let mut segments: Vec<Segment> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let (start, compacted) = match name.strip_suffix(COMPACT_EXT) {
+                    Some(start) => (start, true),
+                    None => (name.strip_suffix(SEGMENT_EXT)?, false),
+                };
+                Some(Segment {
+                    start_ms: start.parse().ok()?,
+                    compacted,
+                    path: entry.path(),
+                })
+            })
+            .collect();
+        segments.sort_by_key(|s| s.start_ms);
+        Ok(segments)
+    }
+
+    /// Records in one segment. A line that does not parse (a write cut off
+    /// by a crash) is skipped.
+    fn read_segment(path: &Path) -> Result<Vec<Record>> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| Error::io(format!("Failed to read {}", path.display()), e))?;
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+
+    /// Samples and cleans with `from_ms <= timestamp < to_ms`, oldest first.
+    pub fn read_range(&self, from_ms: u64, to_ms: u64) -> Result<(Vec<Sample>, Vec<CleanMarker>)> {
+        let segment_ms = ms(self.options.segment_duration);
+        let mut samples = Vec::new();
+        let mut cleans = Vec::new();
+
+        for segment in self.segments()? {
+            if segment.start_ms + segment_ms <= from_ms || segment.start_ms >= to_ms {
+                continue;
+            }
+            for record in Self::read_segment(&segment.path)? {
+                if record.timestamp_ms() < from_ms || record.timestamp_ms() >= to_ms {
+                    continue;
+                }
+                match record {
+                    Record::Sample(sample) => samples.push(sample),
+                    Record::Clean(clean) => cleans.push(clean),
+                }
+            }
+        }
+        samples.sort_by_key(|s| s.timestamp_ms);
+        cleans.sort_by_key(|c| c.timestamp_ms);
+        Ok((samples, cleans))
+    }
+
+    /// Delete segments past `retention` and compact those past
+    /// `compact_after`. Cheap when there is nothing to do.
+    pub fn maintain(&mut self, now_ms: u64) -> Result<()> {
+        let segment_ms = ms(self.options.segment_duration);
+        let retention_cutoff = now_ms.saturating_sub(ms(self.options.retention));
+        let compact_cutoff = now_ms.saturating_sub(ms(self.options.compact_after));
+
+        for segment in self.segments()? {
+            let end_ms = segment.start_ms + segment_ms;
+            if end_ms <= retention_cutoff {
+                fs::remove_file(&segment.path).map_err(|e| {
+                    Error::io(format!("Failed to delete {}", segment.path.display()), e)
+                })?;
+            } else if !segment.compacted && end_ms <= compact_cutoff {
+                self.compact(&segment)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Rewrite a segment with samples averaged to `compact_resolution`.
+    fn compact(&mut self, segment: &Segment) -> Result<()> {
+        if self.current.as_ref().map(|(start, _)| *start) == Some(segment.start_ms) {
+            self.current = None;
+        }
+
+        let mut samples = Vec::new();
+        let mut cleans = Vec::new();
+        for record in Self::read_segment(&segment.path)? {
+            match record {
+                Record::Sample(sample) => samples.push(sample),
+                Record::Clean(clean) => cleans.push(clean),
+            }
+        }
+        samples.sort_by_key(|s| s.timestamp_ms);
+
+        let mut contents = Vec::new();
+        let records = downsample(&samples, ms(self.options.compact_resolution))
+            .into_iter()
+            .map(Record::Sample)
+            .chain(cleans.into_iter().map(Record::Clean));
+        for record in records {
+            serde_json::to_writer(&mut contents, &record)
+                .map_err(|e| Error::invalid_input(e.to_string()))?;
+            contents.push(b'\n');
+        }
+
+        let path = self
+            .dir
+            .join(format!("{}{}", segment.start_ms, COMPACT_EXT));
+        let tmp_path = path.with_extension("jsonl.tmp");
+        let write_err =
+            |e: std::io::Error| Error::io(format!("Failed to write {}", path.display()), e);
+        let mut tmp = File::create(&tmp_path).map_err(write_err)?;
+        tmp.write_all(&contents).map_err(write_err)?;
+        tmp.sync_all().map_err(write_err)?;
+        drop(tmp);
+
+        fs::rename(&tmp_path, &path).map_err(write_err)?;
+        fs::remove_file(&segment.path)
+            .map_err(|e| Error::io(format!("Failed to delete {}", segment.path.display()), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::{History, HistoryOptions};
+    use crate::memory::tests::memory;
+
+    /// A store in its own temp directory, removed on drop. Segments cover
+    /// 10 s, are compacted to 5 s averages after 20 s and deleted after 60 s.
+    struct TempStore(MetricsStore);
+
+    impl TempStore {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("mcm-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            let options = StoreOptions {
+                segment_duration: Duration::from_secs(10),
+                retention: Duration::from_secs(60),
+                compact_after: Duration::from_secs(20),
+                compact_resolution: Duration::from_secs(5),
+            };
+            Self(MetricsStore::open(dir, options).unwrap())
+        }
+
+        fn files(&self) -> Vec<String> {
+            let mut files: Vec<String> = fs::read_dir(self.0.dir())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            files.sort();
+            files
+        }
+    }
+
+    impl Drop for TempStore {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(self.0.dir());
+        }
+    }
+
+    fn sample(timestamp_ms: u64, cache_mb: u64) -> Sample {
+        Sample {
+            timestamp_ms,
+            info: memory(8000, 4000, cache_mb),
+        }
+    }
+
+    fn clean(timestamp_ms: u64) -> CleanMarker {
+        CleanMarker {
+            timestamp_ms,
+            cleaned_mb: 300,
+            automatic: false,
+            duration_ms: 1200,
+        }
+    }
+
+    fn points(samples: &[Sample]) -> Vec<(u64, u64)> {
+        samples
+            .iter()
+            .map(|s| (s.timestamp_ms, s.info.cache_mb))
+            .collect()
+    }
+
+    #[test]
+    fn starts_a_segment_per_duration() {
+        let mut store = TempStore::new("store-segments");
+        for (timestamp_ms, cache_mb) in [(0, 1), (5000, 2), (9999, 3), (10_000, 4), (25_000, 5)] {
+            store
+                .0
+                .append_sample(&sample(timestamp_ms, cache_mb))
+                .unwrap();
+        }
+        store.0.append_clean(&clean(12_000)).unwrap();
+        assert_eq!(store.files(), vec!["0.jsonl", "10000.jsonl", "20000.jsonl"]);
+
+        let (samples, cleans) = store.0.read_range(0, 30_000).unwrap();
+        assert_eq!(samples.len(), 5);
+        assert_eq!(cleans, vec![clean(12_000)]);
+        let (samples, cleans) = store.0.read_range(5000, 10_001).unwrap();
+        assert_eq!(points(&samples), vec![(5000, 2), (9999, 3), (10_000, 4)]);
+        assert!(cleans.is_empty());
+    }
+
+    #[test]
+    fn skips_lines_cut_off_by_a_crash() {
+        let mut store = TempStore::new("store-truncated");
+        store.0.append_sample(&sample(1000, 1)).unwrap();
+        let path = store.0.dir().join("0.jsonl");
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"type":"sample","timestamp_ms":20"#)
+            .unwrap();
+
+        let (samples, _) = store.0.read_range(0, 10_000).unwrap();
+        assert_eq!(points(&samples), vec![(1000, 1)]);
+    }
+
+    #[test]
+    fn compacts_then_deletes_old_segments() {
+        let mut store = TempStore::new("store-maintain");
+        for second in 0..20 {
+            store
+                .0
+                .append_sample(&sample(second * 1000, second))
+                .unwrap();
+        }
+        store.0.append_clean(&clean(3000)).unwrap();
+        store.0.append_sample(&sample(40_000, 40)).unwrap();
+
+        // Both segments ended at least 20 s ago
+        store.0.maintain(40_000).unwrap();
+        assert_eq!(
+            store.files(),
+            vec!["0.compact.jsonl", "10000.compact.jsonl", "40000.jsonl"]
+        );
+        let (samples, cleans) = store.0.read_range(0, 50_000).unwrap();
+        assert_eq!(
+            points(&samples),
+            vec![(0, 2), (5000, 7), (10_000, 12), (15_000, 17), (40_000, 40)]
+        );
+        assert_eq!(cleans, vec![clean(3000)]);
+
+        // Compacting twice changes nothing
+        store.0.maintain(40_000).unwrap();
+        assert_eq!(store.0.read_range(0, 50_000).unwrap().0.len(), 5);
+
+        // 60 s later only the last segment is left, itself compacted
+        store.0.maintain(100_000).unwrap();
+        assert_eq!(store.files(), vec!["40000.compact.jsonl"]);
+        let (samples, cleans) = store.0.read_range(0, 100_000).unwrap();
+        assert_eq!(points(&samples), vec![(40_000, 40)]);
+        assert!(cleans.is_empty());
+    }
+
+    fn history_options() -> HistoryOptions {
+        HistoryOptions {
+            resolution: Duration::from_secs(1),
+            retention: Duration::from_secs(10),
+            coarse_resolution: Duration::from_secs(5),
+            coarse_retention: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn history_reads_older_ranges_from_the_store() {
+        let mut store = TempStore::new("store-history");
+        for second in 0..5 {
+            store
+                .0
+                .append_sample(&sample(second * 1000, second))
+                .unwrap();
+        }
+        let dir = store.0.dir().to_path_buf();
+        let reopened = MetricsStore::open(&dir, store.0.options.clone()).unwrap();
+        let mut history = History::with_store(history_options(), reopened);
+        history.record_at(20_000, memory(8000, 4000, 20)).unwrap();
+
+        let range = history.query(0, 21_000, 0).unwrap();
+        assert_eq!(
+            points(&range.samples),
+            vec![
+                (0, 0),
+                (1000, 1),
+                (2000, 2),
+                (3000, 3),
+                (4000, 4),
+                (20_000, 20)
+            ]
+        );
+        // The new sample went to the store too
+        assert_eq!(store.0.read_range(20_000, 21_000).unwrap().0.len(), 1);
+    }
+
+    #[test]
+    fn history_returns_store_errors() {
+        let store = TempStore::new("store-errors");
+        let dir = store.0.dir().to_path_buf();
+        let mut history = History::with_store(
+            history_options(),
+            MetricsStore::open(&dir, store.0.options.clone()).unwrap(),
+        );
+        history.record_at(20_000, memory(8000, 4000, 20)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        // Kept in memory, but the error is not swallowed
+        assert!(history.record_at(30_000, memory(8000, 4000, 30)).is_err());
+        assert_eq!(history.len(), 2);
+        assert!(history.record_clean(clean(31_000)).is_err());
+        assert_eq!(history.clean_stats().count(), 1);
+        assert!(history.query(0, 40_000, 0).is_err());
+        // Ranges held in memory do not need the store
+        assert_eq!(history.query(20_000, 40_000, 0).unwrap().samples.len(), 2);
+    }
+}
@@ -0,0 +1,180 @@
+// Live memory sampling for frontends, with coalesced updates
+
+use crate::history::{self, Sample};
+use crate::threshold::{Crossing, CrossingWatcher};
+use crate::{Config, Error, MemoryInfo, MemorySource, Result};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Longest gap between updates, so a quiet machine still looks alive.
+pub const UPDATE_HEARTBEAT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MonitorEvent {
+    Update(Sample),
+    ThresholdCrossed(Crossing),
+    /// The source could not be read; sent once per run of failures
+    SourceFailed(Error),
+}
+
+pub type Sink = Box<dyn FnMut(MonitorEvent) + Send>;
+
+/// Decides which samples are worth an update: the first, any that moved
+/// a field by at least `min_change_mb` since the last update, and one per
+/// `UPDATE_HEARTBEAT` otherwise. Drift is measured against the last update,
+/// so small steps still add up to one.
+#[derive(Default, Debug, Clone)]
+pub struct Coalescer {
+    last: Option<(Instant, MemoryInfo)>,
+}
+
+impl Coalescer {
+    pub fn offer(&mut self, now: Instant, info: &MemoryInfo, min_change_mb: u64) -> bool {
+        let due = match &self.last {
+            None => true,
+            Some((at, last)) => {
+                let changed = [
+                    (last.used_mb, info.used_mb),
+                    (last.available_mb, info.available_mb),
+                    (last.cache_mb, info.cache_mb),
+                    (last.total_mb, info.total_mb),
+                ]
+                .iter()
+                .any(|(a, b)| a.abs_diff(*b) >= min_change_mb.max(1));
+                changed || now.duration_since(*at) >= UPDATE_HEARTBEAT
+            }
+        };
+        if due {
+            self.last = Some((now, info.clone()));
+        }
+        due
+    }
+}
+
+/// Samples memory every `update_interval_ms` on its own thread and passes
+/// coalesced updates and threshold crossings to a sink, until stopped or
+/// dropped. The config is re-read on every tick.
+pub struct Monitor {
+    shutdown: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Monitor {
+    pub fn start(
+        source: Arc<dyn MemorySource>,
+        config: Arc<Mutex<Config>>,
+        mut sink: Sink,
+    ) -> Result<Self> {
+        let shutdown = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_shutdown = Arc::clone(&shutdown);
+
+        let handle = std::thread::Builder::new()
+            .name("monitor".to_string())
+            .spawn(move || {
+                let mut coalescer = Coalescer::default();
+                let mut crossings = CrossingWatcher::default();
+                let mut failing = false;
+
+                loop {
+                    let config = config.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                    match source.read() {
+                        Ok(info) => {
+                            failing = false;
+                            for crossing in crossings.update(&config, &info) {
+                                sink(MonitorEvent::ThresholdCrossed(crossing));
+                            }
+                            if coalescer.offer(Instant::now(), &info, config.update_min_change_mb) {
+                                sink(MonitorEvent::Update(Sample {
+                                    timestamp_ms: history::now_ms(),
+                                    info,
+                                }));
+                            }
+                        }
+                        Err(e) => {
+                            if !failing {
+                                sink(MonitorEvent::SourceFailed(e));
+                            }
+                            failing = true;
+                        }
+                    }
+
+                    let interval = Duration::from_millis(config.update_interval_ms.max(100));
+                    let (stopped, wakeup) = &*thread_shutdown;
+                    let stopped = stopped.lock().unwrap_or_else(|e| e.into_inner());
+                    let (stopped, _) = wakeup
+                        .wait_timeout_while(stopped, interval, |stopped| !*stopped)
+                        .unwrap_or_else(|e| e.into_inner());
+                    if *stopped {
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| Error::io("Failed to start the monitor thread", e))?;
+
+        Ok(Self {
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    pub fn stop(&mut self) {
+        let (stopped, wakeup) = &*self.shutdown;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        wakeup.notify_all();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::tests::memory;
+
+    #[test]
+    fn coalescer_sends_the_first_sample() {
+        let mut coalescer = Coalescer::default();
+        assert!(coalescer.offer(Instant::now(), &memory(8192, 4096, 1000), 16));
+    }
+
+    #[test]
+    fn coalescer_adds_up_drift_since_the_last_update() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::default();
+        assert!(coalescer.offer(start, &memory(8192, 4096, 1000), 16));
+        assert!(!coalescer.offer(start, &memory(8192, 4096, 1010), 16));
+        assert!(coalescer.offer(start, &memory(8192, 4096, 1020), 16));
+        // Measured from the 1020 update now, not the 1000 one
+        assert!(!coalescer.offer(start, &memory(8192, 4096, 1030), 16));
+        assert!(coalescer.offer(start, &memory(8192, 4080, 1030), 16));
+    }
+
+    #[test]
+    fn coalescer_sends_a_heartbeat_when_quiet() {
+        let start = Instant::now();
+        let info = memory(8192, 4096, 1000);
+        let mut coalescer = Coalescer::default();
+        assert!(coalescer.offer(start, &info, 16));
+        assert!(!coalescer.offer(start + Duration::from_secs(9), &info, 16));
+        assert!(coalescer.offer(start + UPDATE_HEARTBEAT, &info, 16));
+        assert!(!coalescer.offer(start + UPDATE_HEARTBEAT + Duration::from_secs(1), &info, 16));
+    }
+
+    #[test]
+    fn coalescer_skips_unchanged_samples_with_no_minimum() {
+        let start = Instant::now();
+        let mut coalescer = Coalescer::default();
+        assert!(coalescer.offer(start, &memory(8192, 4096, 1000), 0));
+        assert!(!coalescer.offer(start, &memory(8192, 4096, 1000), 0));
+        assert!(coalescer.offer(start, &memory(8192, 4096, 1001), 0));
+    }
+}
@@ -0,0 +1,326 @@
+// Per-process memory usage, with sorting and filtering
+
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::path::PathBuf;
+
+/// cgroup v2 mount point that `ProcessInfo::cgroup` is relative to.
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+#[cfg(target_os = "linux")]
+pub mod pageout;
+pub mod procfs;
+pub mod rules;
+pub mod trim;
+#[cfg(target_os = "windows")]
+pub mod win32;
+
+pub use procfs::ProcfsProcesses;
+pub use rules::{ProcessDecision, ProcessRule, ProcessRules};
+pub use trim::{TrimMethod, TrimReport, TrimResult};
+#[cfg(target_os = "windows")]
+pub use win32::Win32Processes;
+
+/// Memory used by one process, in kB. Fields the platform or permissions
+/// do not expose are `None`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub name: String,
+    /// Arguments joined with spaces
+    pub cmdline: Option<String>,
+    /// Full path of the executable
+    pub exe: Option<String>,
+    pub uid: Option<u32>,
+    pub user: Option<String>,
+    /// cgroup v2 path below the cgroup root, e.g. `/system.slice/foo.service`
+    pub cgroup: Option<String>,
+    /// Resident set size (working set on Windows)
+    pub rss_kb: u64,
+    /// Proportional set size: shared pages split between their users
+    pub pss_kb: Option<u64>,
+    /// Unique set size: resident pages no other process maps
+    pub uss_kb: Option<u64>,
+    pub swap_kb: Option<u64>,
+    /// Anonymous memory, resident or swapped (private bytes on Windows)
+    pub private_kb: Option<u64>,
+}
+
+impl ProcessInfo {
+    /// The process's cgroup directory under `CGROUP_ROOT`.
+    pub fn cgroup_dir(&self) -> Option<PathBuf> {
+        let cgroup = self.cgroup.as_deref()?;
+        Some(PathBuf::from(CGROUP_ROOT).join(cgroup.trim_start_matches('/')))
+    }
+}
+
+/// Anything that can list processes.
+pub trait ProcessSource: Send + Sync {
+    fn list(&self) -> Result<Vec<ProcessInfo>>;
+
+    /// One process, or `None` if it is gone.
+    fn read(&self, pid: u32) -> Result<Option<ProcessInfo>> {
+        Ok(self.list()?.into_iter().find(|p| p.pid == pid))
+    }
+}
+
+/// A fixed process list, e.g. a captured snapshot.
+impl ProcessSource for Vec<ProcessInfo> {
+    fn list(&self) -> Result<Vec<ProcessInfo>> {
+        Ok(self.clone())
+    }
+}
+
+/// Case-insensitive match of `text` against a pattern where `*` matches any
+/// run of characters and `?` any one character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    // Greedy match, backtracking to the last `*` on a mismatch
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// A process picked by pid, or every process whose name matches a glob.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessTarget {
+    Pid(u32),
+    Name(String),
+}
+
+impl ProcessTarget {
+    /// A number is a pid; anything else is a name pattern.
+    pub fn parse(value: &str) -> Self {
+        match value.parse() {
+            Ok(pid) => ProcessTarget::Pid(pid),
+            Err(_) => ProcessTarget::Name(value.to_string()),
+        }
+    }
+
+    /// Name patterns are checked against the process name and the file name
+    /// of its executable, since Linux truncates names to 15 characters.
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        match self {
+            ProcessTarget::Pid(pid) => process.pid == *pid,
+            ProcessTarget::Name(pattern) => {
+                let exe_name = process
+                    .exe
+                    .as_deref()
+                    .and_then(|exe| exe.rsplit(['/', '\\']).next());
+                glob_match(pattern, &process.name)
+                    || exe_name.is_some_and(|name| glob_match(pattern, name))
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ProcessTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ProcessTarget::Pid(pid) => write!(f, "{}", pid),
+            ProcessTarget::Name(pattern) => f.write_str(pattern),
+        }
+    }
+}
+
+/// Placeholder for platforms without a native process list.
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub struct UnsupportedProcesses;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+impl ProcessSource for UnsupportedProcesses {
+    fn list(&self) -> Result<Vec<ProcessInfo>> {
+        Err(Error::unsupported("Only supported on Windows and Linux"))
+    }
+}
+
+/// The native process list for this platform.
+pub fn native_processes() -> Box<dyn ProcessSource> {
+    #[cfg(target_os = "windows")]
+    let source = Win32Processes;
+    #[cfg(target_os = "linux")]
+    let source = ProcfsProcesses::new();
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    let source = UnsupportedProcesses;
+
+    Box::new(source)
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Rss,
+    Pss,
+    Uss,
+    Swap,
+    Private,
+    Pid,
+    Name,
+}
+
+impl SortKey {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "rss" => Ok(SortKey::Rss),
+            "pss" => Ok(SortKey::Pss),
+            "uss" => Ok(SortKey::Uss),
+            "swap" => Ok(SortKey::Swap),
+            "private" => Ok(SortKey::Private),
+            "pid" => Ok(SortKey::Pid),
+            "name" => Ok(SortKey::Name),
+            _ => Err(Error::invalid_input(format!(
+                "Unknown sort key: {} (expected rss, pss, uss, swap, private, pid or name)",
+                value
+            ))),
+        }
+    }
+}
+
+/// Which processes to return and in what order.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct ProcessQuery {
+    /// Memory keys sort largest first, `pid` and `name` ascending
+    pub sort: SortKey,
+    /// Flip the order `sort` gives
+    pub reverse: bool,
+    /// Case-insensitive substring of the name or command line
+    pub filter: Option<String>,
+    pub min_rss_kb: u64,
+    pub limit: Option<usize>,
+}
+
+impl ProcessQuery {
+    pub fn apply(&self, mut processes: Vec<ProcessInfo>) -> Vec<ProcessInfo> {
+        let filter = self.filter.as_deref().map(str::to_lowercase);
+        processes.retain(|p| {
+            let matches = match &filter {
+                Some(filter) => {
+                    p.name.to_lowercase().contains(filter)
+                        || p.cmdline
+                            .as_deref()
+                            .is_some_and(|cmdline| cmdline.to_lowercase().contains(filter))
+                }
+                None => true,
+            };
+            matches && p.rss_kb >= self.min_rss_kb
+        });
+
+        // Missing values sort as zero; ties fall back to pid for a stable order
+        let memory = |value: Option<u64>| Reverse(value.unwrap_or(0));
+        match self.sort {
+            SortKey::Rss => processes.sort_by_key(|p| (Reverse(p.rss_kb), p.pid)),
+            SortKey::Pss => processes.sort_by_key(|p| (memory(p.pss_kb), p.pid)),
+            SortKey::Uss => processes.sort_by_key(|p| (memory(p.uss_kb), p.pid)),
+            SortKey::Swap => processes.sort_by_key(|p| (memory(p.swap_kb), p.pid)),
+            SortKey::Private => processes.sort_by_key(|p| (memory(p.private_kb), p.pid)),
+            SortKey::Pid => processes.sort_by_key(|p| p.pid),
+            SortKey::Name => processes.sort_by(|a, b| {
+                (a.name.to_lowercase(), a.pid).cmp(&(b.name.to_lowercase(), b.pid))
+            }),
+        }
+        if self.reverse {
+            processes.reverse();
+        }
+        if let Some(limit) = self.limit {
+            processes.truncate(limit);
+        }
+        processes
+    }
+}
+
+/// List processes from `source` and apply `query`.
+pub fn list_processes(
+    source: &dyn ProcessSource,
+    query: &ProcessQuery,
+) -> Result<Vec<ProcessInfo>> {
+    source.list().map(|processes| query.apply(processes))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A process with only a pid and name set.
+    pub(crate) fn process(pid: u32, name: &str) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            name: name.to_string(),
+            cmdline: None,
+            exe: None,
+            uid: None,
+            user: None,
+            cgroup: None,
+            rss_kb: 0,
+            pss_kb: None,
+            uss_kb: None,
+            swap_kb: None,
+            private_kb: None,
+        }
+    }
+
+    #[test]
+    fn glob_matches_stars_at_either_end() {
+        assert!(glob_match("post*", "postgres"));
+        assert!(glob_match("*gres", "postgres"));
+        assert!(glob_match("*stgr*", "postgres"));
+        assert!(glob_match("p*g*s", "postgres"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("**", "postgres"));
+        assert!(!glob_match("post*", "mypostgres"));
+        assert!(!glob_match("*gres", "postgres-14"));
+    }
+
+    #[test]
+    fn glob_question_mark_is_exactly_one_character() {
+        assert!(glob_match("p?stgres", "postgres"));
+        assert!(glob_match("???", "abc"));
+        assert!(!glob_match("???", "ab"));
+        assert!(!glob_match("???", "abcd"));
+        assert!(!glob_match("?", ""));
+    }
+
+    #[test]
+    fn empty_glob_only_matches_empty_text() {
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "a"));
+        assert!(!glob_match("a*", ""));
+    }
+
+    #[test]
+    fn name_targets_use_the_executable_not_the_command_line() {
+        let mut p = process(9, "my-app-server");
+        p.exe = Some("/opt/My App/bin/my-app-server-14".to_string());
+        p.cmdline = Some("/opt/My App/bin/my-app-server-14 --port 80".to_string());
+        assert!(ProcessTarget::parse("my-app-server-14").matches(&p));
+        assert!(ProcessTarget::parse("my-app-*").matches(&p));
+        assert!(!ProcessTarget::parse("my").matches(&p));
+        assert!(!ProcessTarget::parse("App").matches(&p));
+        assert!(ProcessTarget::parse("9").matches(&p));
+    }
+
+    #[test]
+    fn glob_ignores_case() {
+        assert!(glob_match("Firefox*", "firefox-bin"));
+        assert!(glob_match("*.SCOPE", "/user.slice/app.scope"));
+    }
+}
@@ -0,0 +1,96 @@
+// Linux process_madvise(MADV_PAGEOUT) over another process's mappings
+
+use crate::error::{Error, Result};
+use std::ffi::{c_int, c_long, c_void};
+use std::io;
+use std::path::Path;
+
+// Same numbers on every architecture using the generic syscall table
+const SYS_PIDFD_OPEN: c_long = 434;
+const SYS_PROCESS_MADVISE: c_long = 440;
+const MADV_PAGEOUT: c_int = 21;
+
+#[repr(C)]
+struct IoVec {
+    base: *mut c_void,
+    len: usize,
+}
+
+extern "C" {
+    fn syscall(number: c_long, ...) -> c_long;
+    fn close(fd: c_int) -> c_int;
+}
+
+/// Address ranges from /proc/<pid>/maps worth paging out. Kernel-provided
+/// mappings such as [vdso] cannot be advised and are skipped.
+pub fn parse_maps(contents: &str) -> Vec<(usize, usize)> {
+    contents
+        .lines()
+        .filter(|line| {
+            !line.ends_with("[vsyscall]") && !line.ends_with("[vvar]") && !line.ends_with("[vdso]")
+        })
+        .filter_map(|line| {
+            let (start, end) = line.split_whitespace().next()?.split_once('-')?;
+            let start = usize::from_str_radix(start, 16).ok()?;
+            let end = usize::from_str_radix(end, 16).ok()?;
+            (end > start).then_some((start, end - start))
+        })
+        .collect()
+}
+
+fn last_error(context: String) -> Error {
+    let e = io::Error::last_os_error();
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => Error::permission_denied(format!(
+            "{}: {} (needs CAP_SYS_NICE and ptrace access to the process)",
+            context, e
+        )),
+        _ if e.raw_os_error() == Some(38) => {
+            Error::unsupported(format!("{}: process_madvise needs Linux 5.10+", context))
+        }
+        _ => Error::io(context, e),
+    }
+}
+
+/// Ask the kernel to reclaim every mapping of `pid`. Mappings the kernel
+/// refuses, such as locked or special ones, are skipped.
+pub fn pageout(proc_root: &Path, pid: u32) -> Result<()> {
+    let maps_path = proc_root.join(pid.to_string()).join("maps");
+    let maps = std::fs::read_to_string(&maps_path)
+        .map_err(|e| Error::io(format!("Failed to read {}", maps_path.display()), e))?;
+
+    let pidfd = unsafe { syscall(SYS_PIDFD_OPEN, pid as c_int, 0 as c_int) };
+    if pidfd < 0 {
+        return Err(last_error(format!("pidfd_open({}) failed", pid)));
+    }
+    let pidfd = pidfd as c_int;
+
+    let mut result = Ok(());
+    for (start, len) in parse_maps(&maps) {
+        let iov = IoVec {
+            base: start as *mut c_void,
+            len,
+        };
+        let advised = unsafe {
+            syscall(
+                SYS_PROCESS_MADVISE,
+                pidfd,
+                &iov as *const IoVec,
+                1usize,
+                MADV_PAGEOUT,
+                0u32,
+            )
+        };
+        if advised < 0 {
+            let errno = io::Error::last_os_error().raw_os_error();
+            // EINVAL/ENOMEM: a mapping that cannot be paged out or went away
+            if errno == Some(22) || errno == Some(12) {
+                continue;
+            }
+            result = Err(last_error(format!("process_madvise({}) failed", pid)));
+            break;
+        }
+    }
+    unsafe { close(pidfd) };
+    result
+}
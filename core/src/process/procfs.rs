@@ -0,0 +1,353 @@
+// Linux per-process memory from /proc/<pid>/status and smaps_rollup
+
+use super::{ProcessInfo, ProcessSource};
+use crate::error::{Error, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+pub const PROC_ROOT: &str = "/proc";
+pub const PASSWD_PATH: &str = "/etc/passwd";
+
+/// kB value of `key` in a `Key:   123 kB` style file.
+fn kb_field(contents: &str, key: &str) -> Option<u64> {
+    contents.lines().find_map(|line| {
+        let (k, rest) = line.split_once(':')?;
+        if k.trim() != key {
+            return None;
+        }
+        rest.split_whitespace().next()?.parse().ok()
+    })
+}
+
+/// The unified hierarchy path from /proc/<pid>/cgroup contents.
+pub fn parse_cgroup(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|cgroup| cgroup.trim().to_string())
+}
+
+/// uid to user name from /etc/passwd contents.
+pub fn parse_passwd(contents: &str) -> HashMap<u32, String> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse().ok()?;
+            Some((uid, name.to_string()))
+        })
+        .collect()
+}
+
+/// Build a `ProcessInfo` from a process's status, cmdline and smaps_rollup
+/// contents. `exe`, `user` and `cgroup` are left for the caller to fill.
+/// Returns `None` for kernel threads, which have no memory of their own.
+/// `smaps_rollup` is `None` when it could not be read, which is the case
+/// for other users' processes without root.
+pub fn parse_process(
+    pid: u32,
+    status: &str,
+    cmdline: &[u8],
+    smaps_rollup: Option<&str>,
+) -> Option<ProcessInfo> {
+    let rss_kb = kb_field(status, "VmRSS")?;
+    // Real uid, the first of four
+    let uid = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Uid:"))
+        .and_then(|uids| uids.split_whitespace().next()?.parse().ok());
+    let name = status
+        .lines()
+        .find_map(|line| line.strip_prefix("Name:"))
+        .map(|name| name.trim().to_string())
+        .unwrap_or_default();
+    let args: Vec<String> = cmdline
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect();
+
+    let rollup = |key: &str| smaps_rollup.and_then(|contents| kb_field(contents, key));
+    let swap_kb = rollup("Swap").or_else(|| kb_field(status, "VmSwap"));
+    let uss_kb = match (rollup("Private_Clean"), rollup("Private_Dirty")) {
+        (Some(clean), Some(dirty)) => Some(clean + dirty),
+        _ => None,
+    };
+
+    Some(ProcessInfo {
+        pid,
+        name,
+        cmdline: (!args.is_empty()).then(|| args.join(" ")),
+        exe: None,
+        uid,
+        user: None,
+        cgroup: None,
+        rss_kb,
+        pss_kb: rollup("Pss"),
+        uss_kb,
+        swap_kb,
+        private_kb: rollup("Anonymous").map(|anon| anon + swap_kb.unwrap_or(0)),
+    })
+}
+
+/// Lists processes from a procfs tree, /proc unless pointed elsewhere.
+pub struct ProcfsProcesses {
+    root: PathBuf,
+    passwd: PathBuf,
+}
+
+impl ProcfsProcesses {
+    pub fn new() -> Self {
+        Self::with_root(PROC_ROOT)
+    }
+
+    /// Read from a captured or fake procfs tree instead of the live one.
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            passwd: PathBuf::from(PASSWD_PATH),
+        }
+    }
+
+    /// Resolve user names from another passwd file.
+    pub fn with_passwd(mut self, passwd: impl Into<PathBuf>) -> Self {
+        self.passwd = passwd.into();
+        self
+    }
+
+    fn users(&self) -> HashMap<u32, String> {
+        fs::read_to_string(&self.passwd)
+            .map(|contents| parse_passwd(&contents))
+            .unwrap_or_default()
+    }
+
+    fn read_with(&self, pid: u32, users: &HashMap<u32, String>) -> Result<Option<ProcessInfo>> {
+        let dir = self.root.join(pid.to_string());
+        let status = match fs::read_to_string(dir.join("status")) {
+            Ok(status) => status,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(Error::io(
+                    format!("Failed to read {}", dir.join("status").display()),
+                    e,
+                ))
+            }
+        };
+        let cmdline = fs::read(dir.join("cmdline")).unwrap_or_default();
+        let smaps_rollup = fs::read_to_string(dir.join("smaps_rollup")).ok();
+        let Some(mut process) = parse_process(pid, &status, &cmdline, smaps_rollup.as_deref())
+        else {
+            return Ok(None);
+        };
+
+        process.exe = fs::read_link(dir.join("exe"))
+            .ok()
+            .map(|exe| exe.to_string_lossy().into_owned());
+        process.user = process.uid.and_then(|uid| users.get(&uid).cloned());
+        process.cgroup = fs::read_to_string(dir.join("cgroup"))
+            .ok()
+            .and_then(|contents| parse_cgroup(&contents));
+        Ok(Some(process))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+impl Default for ProcfsProcesses {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessSource for ProcfsProcesses {
+    fn list(&self) -> Result<Vec<ProcessInfo>> {
+        let entries = fs::read_dir(&self.root)
+            .map_err(|e| Error::io(format!("Failed to read {}", self.root.display()), e))?;
+        let users = self.users();
+
+        let mut processes = Vec::new();
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let Some(pid) = entry.file_name().to_str().and_then(|n| n.parse().ok()) else {
+                continue;
+            };
+            // Processes exit while we walk and some may be hidden from us;
+            // neither should fail the whole list
+            if let Ok(Some(process)) = self.read_with(pid, &users) {
+                processes.push(process);
+            }
+        }
+        Ok(processes)
+    }
+
+    /// `Ok(None)` if the process exited or is a kernel thread.
+    fn read(&self, pid: u32) -> Result<Option<ProcessInfo>> {
+        self.read_with(pid, &self.users())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STATUS: &str = "\
+Name:\tfirefox
+Umask:\t0022
+State:\tS (sleeping)
+Tgid:\t4242
+Pid:\t4242
+PPid:\t1
+Uid:\t1000\t1000\t1000\t1000
+Gid:\t1000\t1000\t1000\t1000
+VmPeak:\t 3145728 kB
+VmSize:\t 3000000 kB
+VmRSS:\t  524288 kB
+RssAnon:\t  262144 kB
+RssFile:\t  253952 kB
+VmSwap:\t    1024 kB
+Threads:\t42
+";
+
+    const SMAPS_ROLLUP: &str = "\
+55d0c0a00000-7ffd2a5ff000 ---p 00000000 00:00 0                          [rollup]
+Rss:              524288 kB
+Pss:              400000 kB
+Pss_Anon:         250000 kB
+Shared_Clean:     200000 kB
+Shared_Dirty:      24288 kB
+Private_Clean:    100000 kB
+Private_Dirty:    200000 kB
+Anonymous:        250000 kB
+Swap:               2048 kB
+SwapPss:            2048 kB
+";
+
+    // Kernel threads have no mm, so no Vm* lines at all
+    const KTHREAD_STATUS: &str = "\
+Name:\tkworker/0:1-events
+Umask:\t0000
+State:\tI (idle)
+Tgid:\t77
+Pid:\t77
+PPid:\t2
+Uid:\t0\t0\t0\t0
+Gid:\t0\t0\t0\t0
+Threads:\t1
+";
+
+    #[test]
+    fn parses_status_and_smaps_rollup() {
+        let process = parse_process(
+            4242,
+            STATUS,
+            b"/usr/lib/firefox/firefox\0-P\0work\0",
+            Some(SMAPS_ROLLUP),
+        )
+        .unwrap();
+        assert_eq!(process.name, "firefox");
+        assert_eq!(
+            process.cmdline.as_deref(),
+            Some("/usr/lib/firefox/firefox -P work")
+        );
+        assert_eq!(process.uid, Some(1000));
+        assert_eq!(process.rss_kb, 524288);
+        assert_eq!(process.pss_kb, Some(400000));
+        assert_eq!(process.uss_kb, Some(300000));
+        // smaps_rollup's Swap wins over VmSwap
+        assert_eq!(process.swap_kb, Some(2048));
+        assert_eq!(process.private_kb, Some(252048));
+    }
+
+    #[test]
+    fn falls_back_to_status_without_smaps_rollup() {
+        let process = parse_process(4242, STATUS, b"", None).unwrap();
+        assert_eq!(process.cmdline, None);
+        assert_eq!(process.pss_kb, None);
+        assert_eq!(process.uss_kb, None);
+        assert_eq!(process.swap_kb, Some(1024));
+        assert_eq!(process.private_kb, None);
+    }
+
+    #[test]
+    fn skips_kernel_threads() {
+        assert_eq!(parse_process(77, KTHREAD_STATUS, b"", None), None);
+    }
+
+    #[test]
+    fn parses_unified_cgroup() {
+        let contents = "12:memory:/user.slice\n0::/user.slice/user-1000.slice/session-2.scope\n";
+        assert_eq!(
+            parse_cgroup(contents).as_deref(),
+            Some("/user.slice/user-1000.slice/session-2.scope")
+        );
+        assert_eq!(parse_cgroup("12:memory:/user.slice\n"), None);
+    }
+
+    /// A fake procfs tree under the temp dir, removed on drop.
+    struct FakeProc(PathBuf);
+
+    impl FakeProc {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("mcm-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(&root).unwrap();
+            Self(root)
+        }
+
+        fn add(&self, pid: u32, files: &[(&str, &[u8])]) {
+            let dir = self.0.join(pid.to_string());
+            fs::create_dir_all(&dir).unwrap();
+            for (name, contents) in files {
+                fs::write(dir.join(name), contents).unwrap();
+            }
+        }
+    }
+
+    impl Drop for FakeProc {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn lists_a_fake_procfs_tree() {
+        let proc = FakeProc::new("procfs");
+        proc.add(
+            4242,
+            &[
+                ("status", STATUS.as_bytes()),
+                ("cmdline", b"firefox\0"),
+                ("smaps_rollup", SMAPS_ROLLUP.as_bytes()),
+                ("cgroup", b"0::/user.slice/app-firefox.scope\n"),
+            ],
+        );
+        proc.add(77, &[("status", KTHREAD_STATUS.as_bytes())]);
+        fs::write(proc.0.join("meminfo"), "MemTotal: 1024 kB\n").unwrap();
+        let passwd = proc.0.join("passwd");
+        fs::write(
+            &passwd,
+            "root:x:0:0:root:/root:/bin/sh\nalice:x:1000:1000::/home/alice:/bin/sh\n",
+        )
+        .unwrap();
+
+        let source = ProcfsProcesses::with_root(&proc.0).with_passwd(&passwd);
+        let processes = source.list().unwrap();
+        assert_eq!(processes.len(), 1);
+        let firefox = &processes[0];
+        assert_eq!(firefox.pid, 4242);
+        assert_eq!(firefox.user.as_deref(), Some("alice"));
+        assert_eq!(
+            firefox.cgroup.as_deref(),
+            Some("/user.slice/app-firefox.scope")
+        );
+        assert_eq!(firefox.pss_kb, Some(400000));
+
+        assert_eq!(source.read(4242).unwrap().as_ref(), Some(firefox));
+        assert_eq!(source.read(77).unwrap(), None);
+        assert_eq!(source.read(9999).unwrap(), None);
+    }
+}
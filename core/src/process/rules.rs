@@ -0,0 +1,262 @@
+// Allow and deny lists deciding which processes cleaning may act on
+
+use super::{glob_match, ProcessInfo, ProcessTarget};
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// One entry of an allow or deny list. Patterns are case-insensitive globs.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProcessRule {
+    /// Process or executable file name, e.g. `postgres*`
+    Name { pattern: String },
+    /// Full executable path, e.g. `/opt/db/*`
+    Path { pattern: String },
+    /// User name or numeric uid (Linux only)
+    User { user: String },
+    /// cgroup path, e.g. `/system.slice/pipewire*` (Linux only)
+    Cgroup { pattern: String },
+}
+
+impl ProcessRule {
+    /// Parse `name:<glob>`, `path:<glob>`, `user:<name|uid>` or
+    /// `cgroup:<glob>`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = || {
+            Error::invalid_input(format!(
+                "Unknown process rule: {} (expected name:, path:, user: or cgroup: followed by a value)",
+                spec
+            ))
+        };
+        let (kind, value) = spec.split_once(':').ok_or_else(invalid)?;
+        if value.is_empty() {
+            return Err(invalid());
+        }
+        let value = value.to_string();
+        match kind {
+            "name" => Ok(ProcessRule::Name { pattern: value }),
+            "path" => Ok(ProcessRule::Path { pattern: value }),
+            "user" => Ok(ProcessRule::User { user: value }),
+            "cgroup" => Ok(ProcessRule::Cgroup { pattern: value }),
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn matches(&self, process: &ProcessInfo) -> bool {
+        match self {
+            ProcessRule::Name { pattern } => ProcessTarget::Name(pattern.clone()).matches(process),
+            ProcessRule::Path { pattern } => process
+                .exe
+                .as_deref()
+                .is_some_and(|exe| glob_match(pattern, exe)),
+            ProcessRule::User { user } => {
+                process.user.as_deref() == Some(user.as_str())
+                    || process.uid.is_some_and(|uid| uid.to_string() == *user)
+            }
+            ProcessRule::Cgroup { pattern } => process
+                .cgroup
+                .as_deref()
+                .is_some_and(|cgroup| glob_match(pattern, cgroup)),
+        }
+    }
+}
+
+impl fmt::Display for ProcessRule {
+    /// Formats as the spec accepted by `ProcessRule::parse`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcessRule::Name { pattern } => write!(f, "name:{}", pattern),
+            ProcessRule::Path { pattern } => write!(f, "path:{}", pattern),
+            ProcessRule::User { user } => write!(f, "user:{}", user),
+            ProcessRule::Cgroup { pattern } => write!(f, "cgroup:{}", pattern),
+        }
+    }
+}
+
+/// Which processes cleaning may act on: those matching `allow` (or all,
+/// when it is empty) minus those matching `deny`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct ProcessRules {
+    pub allow: Vec<ProcessRule>,
+    pub deny: Vec<ProcessRule>,
+}
+
+impl ProcessRules {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// Why `process` must be left alone, or `None` if it may be touched.
+    pub fn exclusion(&self, process: &ProcessInfo) -> Option<String> {
+        if let Some(rule) = self.deny.iter().find(|rule| rule.matches(process)) {
+            return Some(format!("denied by {}", rule));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(|rule| rule.matches(process)) {
+            return Some("not in the allow list".to_string());
+        }
+        None
+    }
+
+    /// Pair each process with its exclusion reason, if any.
+    pub fn decide(&self, processes: Vec<ProcessInfo>) -> Vec<ProcessDecision> {
+        processes
+            .into_iter()
+            .map(|process| ProcessDecision {
+                excluded: self.exclusion(&process),
+                process,
+            })
+            .collect()
+    }
+}
+
+/// Parse a comma-separated list of rule specs; empty means no rules.
+pub fn parse_rule_list(value: &str) -> Result<Vec<ProcessRule>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|spec| !spec.is_empty())
+        .map(ProcessRule::parse)
+        .collect()
+}
+
+pub fn format_rule_list(rules: &[ProcessRule]) -> String {
+    rules
+        .iter()
+        .map(ProcessRule::to_string)
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// A process an operation would act on, unless `excluded` says why not.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ProcessDecision {
+    #[serde(flatten)]
+    pub process: ProcessInfo,
+    pub excluded: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tests::process;
+
+    fn rule(spec: &str) -> ProcessRule {
+        ProcessRule::parse(spec).unwrap()
+    }
+
+    fn postgres() -> ProcessInfo {
+        let mut p = process(42, "postgres");
+        p.exe = Some("/usr/lib/postgresql/14/bin/postgres".to_string());
+        p.uid = Some(113);
+        p.user = Some("postgres".to_string());
+        p.cgroup = Some("/system.slice/postgresql@14-main.service".to_string());
+        p
+    }
+
+    #[test]
+    fn parses_each_prefix() {
+        assert_eq!(
+            rule("name:postgres*"),
+            ProcessRule::Name {
+                pattern: "postgres*".to_string()
+            }
+        );
+        assert_eq!(
+            rule("path:/opt/*"),
+            ProcessRule::Path {
+                pattern: "/opt/*".to_string()
+            }
+        );
+        assert_eq!(
+            rule("user:1000"),
+            ProcessRule::User {
+                user: "1000".to_string()
+            }
+        );
+        assert_eq!(
+            rule("cgroup:/system.slice/*"),
+            ProcessRule::Cgroup {
+                pattern: "/system.slice/*".to_string()
+            }
+        );
+        for spec in ["postgres", "pid:42", "name:", ""] {
+            assert!(ProcessRule::parse(spec).is_err(), "{}", spec);
+        }
+        let rules = parse_rule_list("name:a, user:root,,cgroup:/x").unwrap();
+        assert_eq!(format_rule_list(&rules), "name:a,user:root,cgroup:/x");
+    }
+
+    #[test]
+    fn each_kind_matches_its_field() {
+        let p = postgres();
+        assert!(rule("name:POSTGRES").matches(&p));
+        assert!(rule("path:/usr/lib/postgresql/*").matches(&p));
+        assert!(!rule("path:postgres").matches(&p));
+        assert!(rule("user:postgres").matches(&p));
+        assert!(rule("user:113").matches(&p));
+        assert!(!rule("user:root").matches(&p));
+        assert!(rule("cgroup:/system.slice/postgresql@*").matches(&p));
+        assert!(!rule("cgroup:/user.slice/*").matches(&p));
+    }
+
+    #[test]
+    fn name_rules_also_match_the_executable_file_name() {
+        // Linux truncates process names to 15 characters
+        let mut p = process(7, "gnome-shell-cal");
+        p.exe = Some("/usr/libexec/gnome-shell-calendar-server".to_string());
+        assert!(rule("name:gnome-shell-calendar-server").matches(&p));
+        assert!(!rule("name:gnome-shell-calendar-server").matches(&process(8, "gnome-shell-cal")));
+    }
+
+    #[test]
+    fn empty_rules_allow_everything() {
+        let rules = ProcessRules::default();
+        assert!(rules.is_empty());
+        assert_eq!(rules.exclusion(&postgres()), None);
+    }
+
+    #[test]
+    fn allow_list_excludes_everything_else() {
+        let rules = ProcessRules {
+            allow: vec![rule("user:1000")],
+            deny: Vec::new(),
+        };
+        let mut mine = process(1, "firefox");
+        mine.uid = Some(1000);
+        assert_eq!(rules.exclusion(&mine), None);
+        assert_eq!(
+            rules.exclusion(&postgres()).as_deref(),
+            Some("not in the allow list")
+        );
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let rules = ProcessRules {
+            allow: vec![rule("user:postgres")],
+            deny: vec![rule("name:postgres")],
+        };
+        assert_eq!(
+            rules.exclusion(&postgres()).as_deref(),
+            Some("denied by name:postgres")
+        );
+    }
+
+    #[test]
+    fn decide_pairs_each_process_with_its_exclusion() {
+        let rules = ProcessRules {
+            allow: Vec::new(),
+            deny: vec![rule("cgroup:/system.slice/*")],
+        };
+        let decisions = rules.decide(vec![postgres(), process(1, "firefox")]);
+        assert_eq!(decisions.len(), 2);
+        assert_eq!(
+            decisions[0].excluded.as_deref(),
+            Some("denied by cgroup:/system.slice/*")
+        );
+        assert_eq!(decisions[1].process.name, "firefox");
+        assert_eq!(decisions[1].excluded, None);
+    }
+}
@@ -0,0 +1,258 @@
+// Trimming other processes' resident memory
+
+use super::{
+    ProcessDecision, ProcessInfo, ProcessRules, ProcessSource, ProcessTarget, CGROUP_ROOT,
+};
+use crate::error::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TrimMethod {
+    /// process_madvise(MADV_PAGEOUT) on Linux, EmptyWorkingSet on Windows
+    #[default]
+    Native,
+    /// Linux cgroup v2 memory.reclaim of the process's RSS from its cgroup.
+    /// Reclaims from every process in that cgroup, not just this one.
+    CgroupReclaim,
+}
+
+impl TrimMethod {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "native" => Ok(TrimMethod::Native),
+            "cgroup-reclaim" => Ok(TrimMethod::CgroupReclaim),
+            _ => Err(Error::invalid_input(format!(
+                "Unknown trim method: {} (expected native or cgroup-reclaim)",
+                value
+            ))),
+        }
+    }
+}
+
+/// Outcome for one process. `error` is set when it could not be trimmed,
+/// most often for lack of permission; the others are still attempted.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TrimResult {
+    pub pid: u32,
+    pub name: String,
+    pub rss_before_kb: u64,
+    /// `None` if the process exited meanwhile
+    pub rss_after_kb: Option<u64>,
+    pub error: Option<Error>,
+}
+
+impl TrimResult {
+    pub fn trimmed_kb(&self) -> u64 {
+        self.rss_after_kb
+            .map_or(0, |after| self.rss_before_kb.saturating_sub(after))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct TrimReport {
+    pub method: TrimMethod,
+    pub processes: Vec<TrimResult>,
+    /// Matching processes left alone because of the process rules
+    pub excluded: Vec<ProcessDecision>,
+}
+
+impl TrimReport {
+    pub fn trimmed_kb(&self) -> u64 {
+        self.processes.iter().map(TrimResult::trimmed_kb).sum()
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn trim_native(process: &ProcessInfo) -> Result<()> {
+    use super::procfs::PROC_ROOT;
+
+    super::pageout::pageout(Path::new(PROC_ROOT), process.pid)
+}
+
+#[cfg(target_os = "windows")]
+fn trim_native(process: &ProcessInfo) -> Result<()> {
+    super::win32::empty_working_set(process.pid)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn trim_native(_process: &ProcessInfo) -> Result<()> {
+    Err(Error::unsupported("Only supported on Windows and Linux"))
+}
+
+#[cfg(target_os = "linux")]
+fn reclaim(dir: &Path, bytes: u64) -> Result<()> {
+    crate::clean::cgroup_reclaim::reclaim(dir, bytes).map(|_| ())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reclaim(_dir: &Path, _bytes: u64) -> Result<()> {
+    Err(Error::unsupported("cgroup reclaim is Linux-only"))
+}
+
+fn not_in_cgroup(process: &ProcessInfo) -> Error {
+    Error::unsupported(format!(
+        "Process {} is not in a cgroup v2 hierarchy",
+        process.pid
+    ))
+}
+
+/// The cgroup `CgroupReclaim` reclaims from for `process`. The root cgroup
+/// is refused, since a reclaim there is a system-wide reclaim.
+fn reclaim_dir(process: &ProcessInfo) -> Result<PathBuf> {
+    let dir = process.cgroup_dir().ok_or_else(|| not_in_cgroup(process))?;
+    if dir == Path::new(CGROUP_ROOT) {
+        return Err(Error::unsupported(format!(
+            "Process {} is in the root cgroup, where reclaim would be system-wide",
+            process.pid
+        )));
+    }
+    Ok(dir)
+}
+
+/// Every process matching `target`, marked with whether `rules` exclude
+/// it. With `CgroupReclaim`, which reclaims from the whole cgroup, a
+/// process is also excluded when its cgroup or one below holds an excluded
+/// process. Fails if nothing matches.
+pub fn preview(
+    source: &dyn ProcessSource,
+    target: &ProcessTarget,
+    method: TrimMethod,
+    rules: &ProcessRules,
+) -> Result<Vec<ProcessDecision>> {
+    let matching: Vec<ProcessInfo> = match target {
+        ProcessTarget::Pid(pid) => source.read(*pid)?.into_iter().collect(),
+        ProcessTarget::Name(_) => source
+            .list()?
+            .into_iter()
+            .filter(|p| target.matches(p))
+            .collect(),
+    };
+    if matching.is_empty() {
+        return Err(Error::invalid_input(format!(
+            "No process matches {}",
+            target
+        )));
+    }
+    let mut decisions = rules.decide(matching);
+    if method == TrimMethod::CgroupReclaim && !rules.is_empty() {
+        let all = source.list()?;
+        for decision in decisions.iter_mut().filter(|d| d.excluded.is_none()) {
+            let Some(dir) = decision.process.cgroup_dir() else {
+                continue;
+            };
+            decision.excluded = all.iter().find_map(|p| {
+                if !p.cgroup_dir().is_some_and(|d| d.starts_with(&dir)) {
+                    return None;
+                }
+                let reason = rules.exclusion(p)?;
+                Some(format!(
+                    "cgroup {} also holds {} ({}, {})",
+                    dir.display(),
+                    p.name,
+                    p.pid,
+                    reason
+                ))
+            });
+        }
+    }
+    Ok(decisions)
+}
+
+/// Trim every process matching `target` that `rules` permit, reading RSS
+/// before and after from `source`. `CgroupReclaim` reclaims each cgroup
+/// once, for the RSS of all its matching processes. Fails only if nothing
+/// matches.
+pub fn trim_processes(
+    source: &dyn ProcessSource,
+    target: &ProcessTarget,
+    method: TrimMethod,
+    rules: &ProcessRules,
+) -> Result<TrimReport> {
+    let (excluded, permitted): (Vec<_>, Vec<_>) = preview(source, target, method, rules)?
+        .into_iter()
+        .partition(|decision| decision.excluded.is_some());
+    let permitted: Vec<ProcessInfo> = permitted.into_iter().map(|d| d.process).collect();
+
+    let errors: Vec<Option<Error>> = match method {
+        TrimMethod::Native => permitted.iter().map(|p| trim_native(p).err()).collect(),
+        TrimMethod::CgroupReclaim => {
+            let mut cgroups: BTreeMap<PathBuf, u64> = BTreeMap::new();
+            for process in &permitted {
+                if let Ok(dir) = reclaim_dir(process) {
+                    *cgroups.entry(dir).or_default() += process.rss_kb * 1024;
+                }
+            }
+            let results: BTreeMap<PathBuf, Option<Error>> = cgroups
+                .into_iter()
+                .map(|(dir, bytes)| {
+                    let error = reclaim(&dir, bytes).err();
+                    (dir, error)
+                })
+                .collect();
+            permitted
+                .iter()
+                .map(|p| match reclaim_dir(p) {
+                    Ok(dir) => results[&dir].clone(),
+                    Err(e) => Some(e),
+                })
+                .collect()
+        }
+    };
+
+    let processes = permitted
+        .into_iter()
+        .zip(errors)
+        .map(|(process, error)| {
+            let rss_after_kb = source.read(process.pid).ok().flatten().map(|p| p.rss_kb);
+            TrimResult {
+                pid: process.pid,
+                name: process.name,
+                rss_before_kb: process.rss_kb,
+                rss_after_kb,
+                error,
+            }
+        })
+        .collect();
+    Ok(TrimReport {
+        method,
+        processes,
+        excluded,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::process::tests::process;
+
+    #[test]
+    fn cgroup_reclaim_refuses_the_root_cgroup() {
+        let mut init = process(1, "systemd");
+        init.cgroup = Some("/".to_string());
+        init.rss_kb = 8192;
+        let loose = process(2, "kthreadd");
+        let source = vec![init, loose];
+
+        let target = ProcessTarget::Name("*".to_string());
+        let report = trim_processes(
+            &source,
+            &target,
+            TrimMethod::CgroupReclaim,
+            &ProcessRules::default(),
+        )
+        .unwrap();
+        assert_eq!(report.processes.len(), 2);
+        for result in &report.processes {
+            assert!(
+                matches!(result.error, Some(Error::Unsupported { .. })),
+                "{:?}",
+                result
+            );
+        }
+        let root = report.processes[0].error.as_ref().unwrap().to_string();
+        assert!(root.contains("root cgroup"), "{}", root);
+    }
+}
@@ -0,0 +1,125 @@
+// Windows per-process memory from the process status API
+
+use super::{ProcessInfo, ProcessSource};
+use crate::error::{Error, Result};
+use crate::source::win32::os_error;
+use windows::core::PWSTR;
+use windows::Win32::Foundation::{CloseHandle, E_ACCESSDENIED};
+use windows::Win32::System::ProcessStatus::*;
+use windows::Win32::System::Threading::*;
+
+/// Lists processes with `EnumProcesses`. Command lines are not read, since
+/// that means reading another process's memory.
+pub struct Win32Processes;
+
+fn process_ids() -> Result<Vec<u32>> {
+    let mut pids = vec![0u32; 1024];
+    loop {
+        let cb = (pids.len() * std::mem::size_of::<u32>()) as u32;
+        let mut needed = 0u32;
+        unsafe { EnumProcesses(pids.as_mut_ptr(), cb, &mut needed) }
+            .map_err(|e| os_error("EnumProcesses failed", e))?;
+        // A full buffer may mean there are more; grow and retry
+        if needed < cb {
+            pids.truncate(needed as usize / std::mem::size_of::<u32>());
+            return Ok(pids);
+        }
+        pids.resize(pids.len() * 2, 0);
+    }
+}
+
+fn read_process(pid: u32) -> Option<ProcessInfo> {
+    let handle = unsafe {
+        OpenProcess(
+            PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_VM_READ,
+            false,
+            pid,
+        )
+    }
+    .ok()?;
+
+    let mut name_buf = [0u16; 1024];
+    let mut name_len = name_buf.len() as u32;
+    let name = unsafe {
+        QueryFullProcessImageNameW(
+            handle,
+            PROCESS_NAME_WIN32,
+            PWSTR(name_buf.as_mut_ptr()),
+            &mut name_len,
+        )
+    }
+    .ok()
+    .map(|_| String::from_utf16_lossy(&name_buf[..name_len as usize]));
+
+    let cb = std::mem::size_of::<PROCESS_MEMORY_COUNTERS_EX>() as u32;
+    let mut counters = PROCESS_MEMORY_COUNTERS_EX {
+        cb,
+        ..Default::default()
+    };
+    let read = unsafe {
+        GetProcessMemoryInfo(
+            handle,
+            &mut counters as *mut PROCESS_MEMORY_COUNTERS_EX as *mut PROCESS_MEMORY_COUNTERS,
+            cb,
+        )
+    };
+    let _ = unsafe { CloseHandle(handle) };
+    read.ok()?;
+
+    Some(ProcessInfo {
+        pid,
+        // The image path's file name, as Task Manager shows it
+        name: name
+            .as_deref()
+            .and_then(|name| name.rsplit('\\').next())
+            .unwrap_or_default()
+            .to_string(),
+        cmdline: None,
+        exe: name,
+        uid: None,
+        user: None,
+        cgroup: None,
+        rss_kb: counters.WorkingSetSize as u64 / 1024,
+        pss_kb: None,
+        uss_kb: None,
+        swap_kb: None,
+        private_kb: Some(counters.PrivateUsage as u64 / 1024),
+    })
+}
+
+impl ProcessSource for Win32Processes {
+    fn list(&self) -> Result<Vec<ProcessInfo>> {
+        // Protected and system processes cannot be opened and are skipped
+        Ok(process_ids()?
+            .into_iter()
+            .filter_map(read_process)
+            .collect())
+    }
+
+    fn read(&self, pid: u32) -> Result<Option<ProcessInfo>> {
+        Ok(read_process(pid))
+    }
+}
+
+/// Trim the working set of another process.
+pub fn empty_working_set(pid: u32) -> Result<()> {
+    let wrap = |context: String, e: windows::core::Error| {
+        if e.code() == E_ACCESSDENIED {
+            Error::permission_denied(format!("{}: access denied", context))
+        } else {
+            os_error(&context, e)
+        }
+    };
+    let handle = unsafe {
+        OpenProcess(
+            PROCESS_SET_QUOTA | PROCESS_QUERY_LIMITED_INFORMATION,
+            false,
+            pid,
+        )
+    }
+    .map_err(|e| wrap(format!("Failed to open process {}", pid), e))?;
+    let result = unsafe { EmptyWorkingSet(handle) }
+        .map_err(|e| wrap(format!("EmptyWorkingSet({}) failed", pid), e));
+    let _ = unsafe { CloseHandle(handle) };
+    result
+}
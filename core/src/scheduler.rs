@@ -0,0 +1,304 @@
+// Background auto-clean loop with start/stop threshold hysteresis
+
+use crate::history::{self, CleanMarker};
+use crate::{Config, Error, History, MemoryInfo, MemorySource, Result};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Cleans `target_mb` using the current config and returns the MB freed.
+pub type Cleaner = Box<dyn FnMut(&Config, u64) -> Result<u64> + Send>;
+
+/// Failures on the auto-clean thread, which has no caller to return them to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchedulerEvent {
+    /// An automatic clean failed
+    CleanFailed(Error),
+    /// The source could not be read; sent once per run of failures
+    SourceFailed(Error),
+    /// History could not be written to its store; sent once per run of
+    /// failures
+    HistoryFailed(Error),
+}
+
+pub type Sink = Box<dyn FnMut(SchedulerEvent) + Send>;
+
+/// Decides when to clean. Cleaning starts once the start threshold is
+/// crossed and keeps going until the stop threshold is reached, so usage
+/// hovering around one threshold does not flap between the two states.
+#[derive(Default, Debug, Clone)]
+pub struct Hysteresis {
+    cleaning: bool,
+}
+
+impl Hysteresis {
+    pub fn is_cleaning(&self) -> bool {
+        self.cleaning
+    }
+
+    /// Feed a sample; returns the MB to clean if a clean is due.
+    pub fn update(&mut self, config: &Config, info: &MemoryInfo) -> Option<u64> {
+        let evaluation = info.evaluate(config);
+        if !config.auto_clean_enabled {
+            self.cleaning = false;
+        } else if evaluation.should_start {
+            self.cleaning = true;
+        } else if evaluation.should_stop {
+            self.cleaning = false;
+        }
+
+        self.cleaning
+            .then_some(evaluation.target_mb)
+            .filter(|&target_mb| target_mb > 0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SchedulerOptions {
+    /// How often memory is sampled.
+    pub sample_interval: Duration,
+    /// Minimum time between two cleans.
+    pub clean_cooldown: Duration,
+}
+
+impl Default for SchedulerOptions {
+    fn default() -> Self {
+        Self {
+            sample_interval: Duration::from_secs(3),
+            clean_cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Runs the auto-clean loop on its own thread until stopped or dropped.
+/// The config is re-read on every tick, so saved changes apply right away.
+/// Every sample and clean is also recorded in `history`, and failures are
+/// passed to `sink`.
+pub struct Scheduler {
+    shutdown: Arc<(Mutex<bool>, Condvar)>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Scheduler {
+    pub fn start(
+        source: Arc<dyn MemorySource>,
+        config: Arc<Mutex<Config>>,
+        history: Arc<Mutex<History>>,
+        mut cleaner: Cleaner,
+        mut sink: Sink,
+        options: SchedulerOptions,
+    ) -> Result<Self> {
+        let shutdown = Arc::new((Mutex::new(false), Condvar::new()));
+        let thread_shutdown = Arc::clone(&shutdown);
+
+        let handle = std::thread::Builder::new()
+            .name("auto-clean".to_string())
+            .spawn(move || {
+                let mut hysteresis = Hysteresis::default();
+                let mut last_clean: Option<Instant> = None;
+                let mut failing = false;
+                let mut history_failing = false;
+                let mut recorded = |result: Result<()>, sink: &mut Sink| match result {
+                    Ok(()) => history_failing = false,
+                    Err(e) => {
+                        if !history_failing {
+                            sink(SchedulerEvent::HistoryFailed(e));
+                        }
+                        history_failing = true;
+                    }
+                };
+
+                loop {
+                    let config = config.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                    match source.read() {
+                        Ok(info) => {
+                            failing = false;
+                            let result = history
+                                .lock()
+                                .unwrap_or_else(|e| e.into_inner())
+                                .record(info.clone());
+                            recorded(result, &mut sink);
+                            let cooled_down = match last_clean {
+                                Some(at) => at.elapsed() >= options.clean_cooldown,
+                                None => true,
+                            };
+                            if let Some(target_mb) = hysteresis.update(&config, &info) {
+                                if cooled_down {
+                                    let started = Instant::now();
+                                    match cleaner(&config, target_mb) {
+                                        Ok(cleaned_mb) => {
+                                            let result = history
+                                                .lock()
+                                                .unwrap_or_else(|e| e.into_inner())
+                                                .record_clean(CleanMarker {
+                                                    timestamp_ms: history::now_ms(),
+                                                    cleaned_mb,
+                                                    automatic: true,
+                                                    duration_ms: started.elapsed().as_millis()
+                                                        as u64,
+                                                });
+                                            recorded(result, &mut sink);
+                                        }
+                                        Err(e) => sink(SchedulerEvent::CleanFailed(e)),
+                                    }
+                                    last_clean = Some(Instant::now());
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            if !failing {
+                                sink(SchedulerEvent::SourceFailed(e));
+                            }
+                            failing = true;
+                        }
+                    }
+
+                    let (stopped, wakeup) = &*thread_shutdown;
+                    let stopped = stopped.lock().unwrap_or_else(|e| e.into_inner());
+                    let (stopped, _) = wakeup
+                        .wait_timeout_while(stopped, options.sample_interval, |stopped| !*stopped)
+                        .unwrap_or_else(|e| e.into_inner());
+                    if *stopped {
+                        break;
+                    }
+                }
+            })
+            .map_err(|e| Error::io("Failed to start the auto-clean thread", e))?;
+
+        Ok(Self {
+            shutdown,
+            handle: Some(handle),
+        })
+    }
+
+    /// Stop the loop and wait for an in-progress clean to finish.
+    pub fn stop(&mut self) {
+        let (stopped, wakeup) = &*self.shutdown;
+        *stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        wakeup.notify_all();
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for Scheduler {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::tests::memory;
+    use crate::source::ScriptedSource;
+    use std::sync::mpsc;
+
+    fn config() -> Config {
+        Config {
+            start_threshold_mb: 1000,
+            stop_threshold_mb: 500,
+            auto_clean_enabled: true,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn cleans_from_start_until_stop() {
+        let config = config();
+        let mut hysteresis = Hysteresis::default();
+        let mut feed = |cache_mb| hysteresis.update(&config, &memory(8000, 4000, cache_mb));
+
+        assert_eq!(feed(800), None);
+        // Start reached: clean down to the stop threshold
+        assert_eq!(feed(1200), Some(700));
+        // Between the thresholds the clean keeps going
+        assert_eq!(feed(900), Some(400));
+        assert_eq!(feed(600), Some(100));
+        assert_eq!(feed(500), None);
+        // Back between the thresholds without crossing start: no clean
+        assert_eq!(feed(900), None);
+        assert_eq!(feed(1000), Some(500));
+    }
+
+    #[test]
+    fn disabling_auto_clean_resets_the_state() {
+        let mut config = config();
+        let mut hysteresis = Hysteresis::default();
+        assert_eq!(
+            hysteresis.update(&config, &memory(8000, 4000, 1200)),
+            Some(700)
+        );
+        assert!(hysteresis.is_cleaning());
+
+        config.auto_clean_enabled = false;
+        assert_eq!(hysteresis.update(&config, &memory(8000, 4000, 1200)), None);
+        assert!(!hysteresis.is_cleaning());
+
+        config.auto_clean_enabled = true;
+        assert_eq!(hysteresis.update(&config, &memory(8000, 4000, 900)), None);
+    }
+
+    #[test]
+    fn available_below_mode_cleans_until_available_is_back() {
+        let config = Config {
+            threshold_mode: crate::ThresholdMode::AvailableBelowMb,
+            start_threshold_mb: 1000,
+            stop_threshold_mb: 2000,
+            auto_clean_enabled: true,
+            ..Config::default()
+        };
+        let mut hysteresis = Hysteresis::default();
+        let mut feed = |available_mb| hysteresis.update(&config, &memory(8000, available_mb, 0));
+
+        assert_eq!(feed(1500), None);
+        assert_eq!(feed(900), Some(1100));
+        assert_eq!(feed(1500), Some(500));
+        assert_eq!(feed(2000), None);
+    }
+
+    #[test]
+    fn reports_failures_to_the_sink() {
+        let source = Arc::new(ScriptedSource::with_results(vec![
+            Err(Error::invalid_input("meminfo went away")),
+            Err(Error::invalid_input("meminfo went away")),
+            Ok(memory(8000, 4000, 1200)),
+        ]));
+        let (targets_tx, targets) = mpsc::channel();
+        let (events_tx, events) = mpsc::channel();
+        let history = Arc::new(Mutex::new(History::default()));
+        let _scheduler = Scheduler::start(
+            source,
+            Arc::new(Mutex::new(config())),
+            Arc::clone(&history),
+            Box::new(move |_: &Config, target_mb| {
+                let _ = targets_tx.send(target_mb);
+                Err(Error::permission_denied("no access"))
+            }),
+            Box::new(move |event| {
+                let _ = events_tx.send(event);
+            }),
+            SchedulerOptions {
+                sample_interval: Duration::from_millis(1),
+                clean_cooldown: Duration::from_secs(60),
+            },
+        )
+        .unwrap();
+
+        let wait = Duration::from_secs(5);
+        assert_eq!(
+            events.recv_timeout(wait).unwrap(),
+            SchedulerEvent::SourceFailed(Error::invalid_input("meminfo went away"))
+        );
+        assert_eq!(targets.recv_timeout(wait).unwrap(), 700);
+        assert_eq!(
+            events.recv_timeout(wait).unwrap(),
+            SchedulerEvent::CleanFailed(Error::permission_denied("no access"))
+        );
+        // Failed cleans leave no marker, and the cooldown holds off a retry
+        assert_eq!(history.lock().unwrap().clean_stats().count(), 0);
+        assert!(targets.recv_timeout(Duration::from_millis(50)).is_err());
+    }
+}
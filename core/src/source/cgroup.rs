@@ -0,0 +1,264 @@
+// cgroup v2 memory readings (memory.current, memory.max, memory.stat)
+
+use super::procfs::ProcfsSource;
+use super::MemorySource;
+use crate::error::{Error, Result};
+use crate::MemoryInfo;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const MB: u64 = 1024 * 1024;
+
+/// Byte counters from a cgroup's memory.stat that feed `MemoryInfo`.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct CgroupStat {
+    pub file: u64,
+    pub shmem: u64,
+    pub slab_reclaimable: u64,
+}
+
+impl CgroupStat {
+    /// Parse memory.stat ("key value" per line, values in bytes).
+    pub fn parse(contents: &str) -> Result<Self> {
+        let values = parse_flat_keyed(contents)?;
+        let get = |key: &str| values.get(key).copied().unwrap_or(0);
+
+        Ok(CgroupStat {
+            file: get("file"),
+            shmem: get("shmem"),
+            slab_reclaimable: get("slab_reclaimable"),
+        })
+    }
+}
+
+/// Parse a flat-keyed cgroup file such as memory.stat into a map.
+pub fn parse_flat_keyed(contents: &str) -> Result<HashMap<String, u64>> {
+    let mut values = HashMap::new();
+    for line in contents.lines() {
+        let mut parts = line.split_whitespace();
+        let (Some(key), Some(value)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let value: u64 = value.parse().map_err(|_| {
+            Error::invalid_input(format!(
+                "Invalid value for {} in memory.stat: {}",
+                key, value
+            ))
+        })?;
+        values.insert(key.to_string(), value);
+    }
+    Ok(values)
+}
+
+/// Parse memory.max; "max" means unlimited and yields `None`.
+pub fn parse_memory_max(contents: &str) -> Result<Option<u64>> {
+    match contents.trim() {
+        "max" => Ok(None),
+        value => value
+            .parse()
+            .map(Some)
+            .map_err(|_| Error::invalid_input(format!("Invalid memory.max: {}", value))),
+    }
+}
+
+/// Build a `MemoryInfo` for a cgroup. `host_total_mb` stands in for the
+/// limit when memory.max is "max".
+pub fn cgroup_memory_info(
+    current: &str,
+    max: &str,
+    stat: &str,
+    host_total_mb: u64,
+) -> Result<MemoryInfo> {
+    let current_bytes: u64 = current
+        .trim()
+        .parse()
+        .map_err(|_| Error::invalid_input(format!("Invalid memory.current: {}", current.trim())))?;
+    let limit_mb = parse_memory_max(max)?
+        .map(|bytes| bytes / MB)
+        .unwrap_or(host_total_mb);
+    let stat = CgroupStat::parse(stat)?;
+
+    let total_mb = limit_mb.min(host_total_mb.max(1));
+    let page_cache_mb = stat.file.saturating_sub(stat.shmem) / MB;
+    let reclaimable_slab_mb = stat.slab_reclaimable / MB;
+    let cache_mb = page_cache_mb + reclaimable_slab_mb;
+
+    // Headroom under the limit plus whatever the kernel can reclaim from
+    // this cgroup, mirroring how MemAvailable treats cache.
+    let used_mb = (current_bytes / MB).saturating_sub(cache_mb).min(total_mb);
+    let available_mb = total_mb - used_mb;
+    let usage_percent = (used_mb as f32 / total_mb.max(1) as f32) * 100.0;
+
+    Ok(MemoryInfo {
+        total_mb,
+        available_mb,
+        used_mb,
+        cache_mb,
+        usage_percent,
+        page_cache_mb,
+        buffers_mb: None,
+        reclaimable_slab_mb: Some(reclaimable_slab_mb),
+        shared_mb: Some(stat.shmem / MB),
+        standby_mb: None,
+        modified_mb: None,
+    })
+}
+
+/// Reads memory accounting for one cgroup v2 directory, e.g.
+/// /sys/fs/cgroup/system.slice/postgresql.service.
+pub struct CgroupSource {
+    path: PathBuf,
+    host: ProcfsSource,
+}
+
+impl CgroupSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            host: ProcfsSource::new(),
+        }
+    }
+
+    /// Use a different meminfo file for the host total (for fixtures).
+    pub fn with_host(mut self, host: ProcfsSource) -> Self {
+        self.host = host;
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn read_file(&self, name: &str) -> Result<String> {
+        let path = self.path.join(name);
+        std::fs::read_to_string(&path)
+            .map_err(|e| Error::io(format!("Failed to read {}", path.display()), e))
+    }
+}
+
+impl MemorySource for CgroupSource {
+    fn name(&self) -> &str {
+        "cgroup"
+    }
+
+    fn read(&self) -> Result<MemoryInfo> {
+        let host_total_mb = self.host.read()?.total_mb;
+        cgroup_memory_info(
+            &self.read_file("memory.current")?,
+            &self.read_file("memory.max")?,
+            &self.read_file("memory.stat")?,
+            host_total_mb,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // memory.stat layout from cgroup v2, shortened; byte values chosen so
+    // the MB figures come out even
+    const STAT: &str = "\
+anon 1073741824
+file 2147483648
+kernel_stack 1327104
+shmem 268435456
+file_mapped 52428800
+slab_reclaimable 104857600
+slab_unreclaimable 20971520
+pgfault 482113
+";
+
+    // 3 GiB charged to the cgroup
+    const CURRENT: &str = "3221225472\n";
+
+    #[test]
+    fn parses_memory_stat() {
+        let stat = CgroupStat::parse(STAT).unwrap();
+        assert_eq!(
+            stat,
+            CgroupStat {
+                file: 2147483648,
+                shmem: 268435456,
+                slab_reclaimable: 104857600,
+            }
+        );
+        assert_eq!(parse_flat_keyed(STAT).unwrap()["pgfault"], 482113);
+        assert_eq!(CgroupStat::parse("").unwrap(), CgroupStat::default());
+    }
+
+    #[test]
+    fn parses_memory_max() {
+        assert_eq!(parse_memory_max("max\n").unwrap(), None);
+        assert_eq!(parse_memory_max("4294967296\n").unwrap(), Some(4294967296));
+        assert!(parse_memory_max("lots").is_err());
+    }
+
+    #[test]
+    fn unlimited_cgroup_uses_host_total() {
+        let memory = cgroup_memory_info(CURRENT, "max\n", STAT, 16384).unwrap();
+        assert_eq!(memory.total_mb, 16384);
+        // file - shmem, plus reclaimable slab
+        assert_eq!(memory.page_cache_mb, 1792);
+        assert_eq!(memory.reclaimable_slab_mb, Some(100));
+        assert_eq!(memory.cache_mb, 1892);
+        assert_eq!(memory.shared_mb, Some(256));
+        // current minus what can be reclaimed
+        assert_eq!(memory.used_mb, 1180);
+        assert_eq!(memory.available_mb, 15204);
+        assert_eq!(memory.buffers_mb, None);
+    }
+
+    #[test]
+    fn limited_cgroup_uses_memory_max() {
+        let memory = cgroup_memory_info(CURRENT, "4294967296\n", STAT, 16384).unwrap();
+        assert_eq!(memory.total_mb, 4096);
+        assert_eq!(memory.used_mb, 1180);
+        assert_eq!(memory.available_mb, 2916);
+
+        // A limit above physical memory is capped at the host total
+        let memory = cgroup_memory_info(CURRENT, "34359738368\n", STAT, 16384).unwrap();
+        assert_eq!(memory.total_mb, 16384);
+    }
+
+    #[test]
+    fn usage_over_the_limit_leaves_nothing_available() {
+        let memory = cgroup_memory_info("5368709120\n", "4294967296\n", "", 16384).unwrap();
+        assert_eq!(memory.used_mb, 4096);
+        assert_eq!(memory.available_mb, 0);
+        assert_eq!(memory.cache_mb, 0);
+        assert_eq!(memory.usage_percent, 100.0);
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        for (current, max, stat) in [
+            ("", "max", STAT),
+            ("lots", "max", STAT),
+            (CURRENT, "unlimited", STAT),
+            (CURRENT, "max", "file lots\n"),
+        ] {
+            let err = cgroup_memory_info(current, max, stat, 16384).unwrap_err();
+            assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+        }
+    }
+
+    #[test]
+    fn reads_a_cgroup_directory() {
+        let dir = std::env::temp_dir().join(format!("mcm-cgroup-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("memory.current"), CURRENT).unwrap();
+        std::fs::write(dir.join("memory.max"), "max\n").unwrap();
+        std::fs::write(dir.join("memory.stat"), STAT).unwrap();
+        std::fs::write(dir.join("meminfo"), "MemTotal: 16777216 kB\n").unwrap();
+
+        let source =
+            CgroupSource::new(&dir).with_host(ProcfsSource::with_path(dir.join("meminfo")));
+        let memory = source.read();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(memory.unwrap().available_mb, 15204);
+
+        let missing = source.read().unwrap_err();
+        assert!(matches!(missing, Error::OsError { .. }), "{:?}", missing);
+    }
+}
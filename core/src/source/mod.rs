@@ -0,0 +1,84 @@
+// Pluggable providers for memory readings
+
+use crate::error::{Error, Result};
+use crate::MemoryInfo;
+use std::path::Path;
+
+pub mod cgroup;
+pub mod procfs;
+pub mod scripted;
+#[cfg(target_os = "windows")]
+pub mod win32;
+
+pub use cgroup::CgroupSource;
+pub use procfs::ProcfsSource;
+pub use scripted::ScriptedSource;
+#[cfg(target_os = "windows")]
+pub use win32::Win32Source;
+
+/// Environment variable that overrides the default source, see `from_spec`.
+pub const SOURCE_ENV: &str = "MCM_MEMORY_SOURCE";
+
+/// Anything that can produce a `MemoryInfo` snapshot.
+pub trait MemorySource: Send + Sync {
+    /// Short identifier shown in logs and errors.
+    fn name(&self) -> &str;
+
+    fn read(&self) -> Result<MemoryInfo>;
+}
+
+/// Placeholder for platforms without a native source.
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub struct UnsupportedSource;
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+impl MemorySource for UnsupportedSource {
+    fn name(&self) -> &str {
+        "unsupported"
+    }
+
+    fn read(&self) -> Result<MemoryInfo> {
+        Err(Error::unsupported("Only supported on Windows and Linux"))
+    }
+}
+
+/// The native source for this platform.
+pub fn native_source() -> Box<dyn MemorySource> {
+    #[cfg(target_os = "windows")]
+    let source = Win32Source;
+    #[cfg(target_os = "linux")]
+    let source = ProcfsSource::new();
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    let source = UnsupportedSource;
+
+    Box::new(source)
+}
+
+/// Build a source from a spec string:
+/// `native`, `procfs[:<meminfo file>]`, `cgroup:<dir>` or `scripted:<json file>`.
+pub fn from_spec(spec: &str) -> Result<Box<dyn MemorySource>> {
+    let (kind, arg) = match spec.split_once(':') {
+        Some((kind, arg)) => (kind, Some(arg)),
+        None => (spec, None),
+    };
+
+    match (kind, arg) {
+        ("native", None) => Ok(native_source()),
+        ("procfs", None) => Ok(Box::new(ProcfsSource::new())),
+        ("procfs", Some(path)) => Ok(Box::new(ProcfsSource::with_path(path))),
+        ("cgroup", Some(path)) => Ok(Box::new(CgroupSource::new(path))),
+        ("scripted", Some(path)) => Ok(Box::new(ScriptedSource::from_json_file(Path::new(path))?)),
+        _ => Err(Error::invalid_input(format!(
+            "Unknown memory source: {}",
+            spec
+        ))),
+    }
+}
+
+/// The source selected by `MCM_MEMORY_SOURCE`, or the native one.
+pub fn default_source() -> Result<Box<dyn MemorySource>> {
+    match std::env::var(SOURCE_ENV) {
+        Ok(spec) if !spec.is_empty() => from_spec(&spec),
+        _ => Ok(native_source()),
+    }
+}
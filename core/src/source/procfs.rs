@@ -0,0 +1,223 @@
+// Linux memory readings from /proc/meminfo
+
+use super::MemorySource;
+use crate::error::{Error, Result};
+use crate::MemoryInfo;
+use std::path::PathBuf;
+
+pub const MEMINFO_PATH: &str = "/proc/meminfo";
+
+/// Raw /proc/meminfo fields we care about, in kB as reported by the kernel.
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Meminfo {
+    pub mem_total_kb: u64,
+    pub mem_free_kb: u64,
+    pub mem_available_kb: Option<u64>,
+    pub buffers_kb: u64,
+    pub cached_kb: u64,
+    pub s_reclaimable_kb: u64,
+    pub shmem_kb: u64,
+}
+
+impl Meminfo {
+    /// Parse the contents of /proc/meminfo. Unknown keys are ignored.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut meminfo = Meminfo::default();
+        let mut has_total = false;
+
+        for line in contents.lines() {
+            let Some((key, rest)) = line.split_once(':') else {
+                continue;
+            };
+            let Some(value) = rest.split_whitespace().next() else {
+                continue;
+            };
+            let value: u64 = value.parse().map_err(|_| {
+                Error::invalid_input(format!("Invalid value for {} in meminfo: {}", key, value))
+            })?;
+
+            match key.trim() {
+                "MemTotal" => {
+                    meminfo.mem_total_kb = value;
+                    has_total = true;
+                }
+                "MemFree" => meminfo.mem_free_kb = value,
+                "MemAvailable" => meminfo.mem_available_kb = Some(value),
+                "Buffers" => meminfo.buffers_kb = value,
+                "Cached" => meminfo.cached_kb = value,
+                "SReclaimable" => meminfo.s_reclaimable_kb = value,
+                "Shmem" => meminfo.shmem_kb = value,
+                _ => {}
+            }
+        }
+
+        if !has_total || meminfo.mem_total_kb == 0 {
+            return Err(Error::invalid_input("MemTotal missing from meminfo"));
+        }
+
+        Ok(meminfo)
+    }
+
+    /// Convert to the summary shown in the UI.
+    pub fn to_memory_info(&self) -> MemoryInfo {
+        // Kernels before 3.14 have no MemAvailable; approximate it the way
+        // `free` used to.
+        let available_kb = self
+            .mem_available_kb
+            .unwrap_or(self.mem_free_kb + self.buffers_kb + self.cached_kb)
+            .min(self.mem_total_kb);
+
+        let total_mb = self.mem_total_kb / 1024;
+        let available_mb = available_kb / 1024;
+        let used_mb = total_mb.saturating_sub(available_mb);
+        // "Cached" includes shmem/tmpfs pages, which only go away when
+        // their files are removed, so they are reported separately.
+        let page_cache_kb = self.cached_kb.saturating_sub(self.shmem_kb);
+        let cache_mb = (page_cache_kb + self.buffers_kb + self.s_reclaimable_kb) / 1024;
+        let usage_percent = (used_mb as f32 / total_mb.max(1) as f32) * 100.0;
+
+        MemoryInfo {
+            total_mb,
+            available_mb,
+            used_mb,
+            cache_mb,
+            usage_percent,
+            page_cache_mb: page_cache_kb / 1024,
+            buffers_mb: Some(self.buffers_kb / 1024),
+            reclaimable_slab_mb: Some(self.s_reclaimable_kb / 1024),
+            shared_mb: Some(self.shmem_kb / 1024),
+            standby_mb: None,
+            modified_mb: None,
+        }
+    }
+}
+
+/// Parse /proc/meminfo contents straight into a `MemoryInfo`.
+pub fn parse_meminfo(contents: &str) -> Result<MemoryInfo> {
+    Meminfo::parse(contents).map(|m| m.to_memory_info())
+}
+
+/// Reads a meminfo-format file, /proc/meminfo unless pointed elsewhere.
+pub struct ProcfsSource {
+    path: PathBuf,
+}
+
+impl ProcfsSource {
+    pub fn new() -> Self {
+        Self::with_path(MEMINFO_PATH)
+    }
+
+    /// Read from a captured meminfo file instead of the live one.
+    pub fn with_path(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl Default for ProcfsSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemorySource for ProcfsSource {
+    fn name(&self) -> &str {
+        "procfs"
+    }
+
+    fn read(&self) -> Result<MemoryInfo> {
+        let contents = std::fs::read_to_string(&self.path)
+            .map_err(|e| Error::io(format!("Failed to read {}", self.path.display()), e))?;
+        parse_meminfo(&contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Laid out like a 5.15 /proc/meminfo, shortened; values are made up
+    const MEMINFO: &str = "\
+MemTotal:       16303412 kB
+MemFree:         8123456 kB
+MemAvailable:   12345678 kB
+Buffers:          204800 kB
+Cached:          4096000 kB
+SwapCached:            0 kB
+Active:          3145728 kB
+Inactive:        2097152 kB
+Shmem:            409600 kB
+KReclaimable:     307200 kB
+Slab:             512000 kB
+SReclaimable:     307200 kB
+SUnreclaim:       204800 kB
+HugePages_Total:       0
+HugePages_Free:        0
+Hugepagesize:       2048 kB
+";
+
+    // Laid out like a 3.10 /proc/meminfo, which has no MemAvailable line;
+    // values are made up
+    const MEMINFO_PRE_3_14: &str = "\
+MemTotal:        4046844 kB
+MemFree:         1024000 kB
+Buffers:          102400 kB
+Cached:          1048576 kB
+SwapCached:            0 kB
+Shmem:             51200 kB
+Slab:              81920 kB
+SReclaimable:      51200 kB
+SUnreclaim:        30720 kB
+";
+
+    #[test]
+    fn parses_meminfo() {
+        let meminfo = Meminfo::parse(MEMINFO).unwrap();
+        assert_eq!(meminfo.mem_available_kb, Some(12345678));
+        assert_eq!(meminfo.s_reclaimable_kb, 307200);
+
+        let memory = meminfo.to_memory_info();
+        assert_eq!(memory.total_mb, 15921);
+        assert_eq!(memory.available_mb, 12056);
+        assert_eq!(memory.used_mb, 3865);
+        assert_eq!(memory.page_cache_mb, 3600);
+        assert_eq!(memory.cache_mb, 4100);
+        assert_eq!(memory.buffers_mb, Some(200));
+        assert_eq!(memory.reclaimable_slab_mb, Some(300));
+        assert_eq!(memory.shared_mb, Some(400));
+        assert_eq!(memory.standby_mb, None);
+    }
+
+    #[test]
+    fn approximates_available_without_memavailable() {
+        let memory = parse_meminfo(MEMINFO_PRE_3_14).unwrap();
+        // MemFree + Buffers + Cached
+        assert_eq!(memory.available_mb, 2124);
+        assert_eq!(memory.total_mb, 3951);
+        assert_eq!(memory.used_mb, 1827);
+        assert_eq!(memory.cache_mb, 1124);
+    }
+
+    #[test]
+    fn rejects_meminfo_without_total() {
+        let err = Meminfo::parse("MemFree:  1024 kB\nCached:  2048 kB\n").unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let err = Meminfo::parse("MemTotal:  lots kB\n").unwrap_err();
+        assert!(matches!(err, Error::InvalidInput { .. }), "{:?}", err);
+    }
+
+    #[test]
+    fn reads_a_meminfo_file() {
+        let path = std::env::temp_dir().join(format!("mcm-meminfo-{}", std::process::id()));
+        std::fs::write(&path, MEMINFO).unwrap();
+        let memory = ProcfsSource::with_path(&path).read();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(memory.unwrap().total_mb, 15921);
+
+        let missing = ProcfsSource::with_path(&path).read().unwrap_err();
+        assert!(matches!(missing, Error::OsError { .. }), "{:?}", missing);
+    }
+}
@@ -0,0 +1,71 @@
+// Scripted memory readings for tests and UI development
+
+use super::MemorySource;
+use crate::error::{Error, Result};
+use crate::MemoryInfo;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// Plays back a fixed sequence of readings, repeating the last one once the
+/// script runs out. An `Err` entry makes that read fail.
+pub struct ScriptedSource {
+    script: Mutex<VecDeque<Result<MemoryInfo>>>,
+    last: Mutex<Option<MemoryInfo>>,
+}
+
+impl ScriptedSource {
+    pub fn new(samples: Vec<MemoryInfo>) -> Self {
+        Self::with_results(samples.into_iter().map(Ok).collect())
+    }
+
+    pub fn with_results(script: Vec<Result<MemoryInfo>>) -> Self {
+        Self {
+            script: Mutex::new(script.into()),
+            last: Mutex::new(None),
+        }
+    }
+
+    /// Load a JSON array of `MemoryInfo` samples.
+    pub fn from_json_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| Error::io(format!("Failed to read {}", path.display()), e))?;
+        let samples: Vec<MemoryInfo> = serde_json::from_str(&contents).map_err(|e| {
+            Error::invalid_input(format!("Invalid memory script {}: {}", path.display(), e))
+        })?;
+        Ok(Self::new(samples))
+    }
+
+    /// Append a reading to the end of the script.
+    pub fn push(&self, reading: Result<MemoryInfo>) {
+        self.script
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push_back(reading);
+    }
+}
+
+impl MemorySource for ScriptedSource {
+    fn name(&self) -> &str {
+        "scripted"
+    }
+
+    fn read(&self) -> Result<MemoryInfo> {
+        let next = self
+            .script
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop_front();
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        match next {
+            Some(Ok(info)) => {
+                *last = Some(info.clone());
+                Ok(info)
+            }
+            Some(Err(e)) => Err(e),
+            None => last
+                .clone()
+                .ok_or_else(|| Error::invalid_input("Memory script is empty")),
+        }
+    }
+}
@@ -0,0 +1,127 @@
+// Windows memory readings from the Win32 and native APIs
+
+use super::MemorySource;
+use crate::error::{Error, Result};
+use crate::MemoryInfo;
+use std::ffi::c_void;
+use windows::Win32::System::Memory::*;
+use windows::Win32::System::ProcessStatus::*;
+use windows::Win32::System::SystemInformation::*;
+
+const MB: u64 = 1024 * 1024;
+
+// SYSTEM_INFORMATION_CLASS value for SystemMemoryListInformation. Not
+// exposed by windows-rs, so the query goes straight to ntdll.
+const SYSTEM_MEMORY_LIST_INFORMATION: u32 = 80;
+
+#[repr(C)]
+#[derive(Default)]
+struct SystemMemoryListInformation {
+    zero_page_count: usize,
+    free_page_count: usize,
+    modified_page_count: usize,
+    modified_no_write_page_count: usize,
+    bad_page_count: usize,
+    page_count_by_priority: [usize; 8],
+    repurposed_pages_by_priority: [usize; 8],
+    modified_page_count_page_file: usize,
+}
+
+#[link(name = "ntdll")]
+extern "system" {
+    fn NtQuerySystemInformation(
+        system_information_class: u32,
+        system_information: *mut c_void,
+        system_information_length: u32,
+        return_length: *mut u32,
+    ) -> i32;
+}
+
+/// Wrap a windows-rs error, keeping its HRESULT as the code.
+pub(crate) fn os_error(context: &str, e: windows::core::Error) -> Error {
+    Error::OsError {
+        code: e.code().0,
+        message: format!("{}: {}", context, e.message()),
+    }
+}
+
+/// Standby and modified page list sizes in MB, or `None` if the query fails.
+fn memory_lists(page_size: u64) -> Option<(u64, u64)> {
+    let mut info = SystemMemoryListInformation::default();
+    let status = unsafe {
+        NtQuerySystemInformation(
+            SYSTEM_MEMORY_LIST_INFORMATION,
+            &mut info as *mut _ as *mut c_void,
+            std::mem::size_of::<SystemMemoryListInformation>() as u32,
+            std::ptr::null_mut(),
+        )
+    };
+    if status < 0 {
+        return None;
+    }
+
+    let standby_pages: usize = info.page_count_by_priority.iter().sum();
+    let standby_mb = standby_pages as u64 * page_size / MB;
+    let modified_mb = info.modified_page_count as u64 * page_size / MB;
+    Some((standby_mb, modified_mb))
+}
+
+/// Live readings from GlobalMemoryStatusEx, GetPerformanceInfo and the
+/// kernel's memory lists.
+#[derive(Default)]
+pub struct Win32Source;
+
+impl MemorySource for Win32Source {
+    fn name(&self) -> &str {
+        "windows"
+    }
+
+    fn read(&self) -> Result<MemoryInfo> {
+        read_memory_info()
+    }
+}
+
+fn read_memory_info() -> Result<MemoryInfo> {
+    let mut mem_status = MEMORYSTATUSEX {
+        dwLength: std::mem::size_of::<MEMORYSTATUSEX>() as u32,
+        ..Default::default()
+    };
+    unsafe { GlobalMemoryStatusEx(&mut mem_status) }
+        .map_err(|e| os_error("Failed to get memory status", e))?;
+
+    let mut perf = PERFORMANCE_INFORMATION {
+        cb: std::mem::size_of::<PERFORMANCE_INFORMATION>() as u32,
+        ..Default::default()
+    };
+    unsafe { GetPerformanceInfo(&mut perf, perf.cb) }
+        .map_err(|e| os_error("Failed to get performance information", e))?;
+
+    let page_size = perf.PageSize as u64;
+    let total_mb = mem_status.ullTotalPhys / MB;
+    let available_mb = mem_status.ullAvailPhys / MB;
+    let used_mb = total_mb.saturating_sub(available_mb);
+    let page_cache_mb = perf.SystemCache as u64 * page_size / MB;
+    let lists = memory_lists(page_size);
+
+    // Task Manager's "Cached" is standby + modified; fall back to the system
+    // cache working set when the lists cannot be queried.
+    let cache_mb = match lists {
+        Some((standby_mb, modified_mb)) => standby_mb + modified_mb,
+        None => page_cache_mb,
+    };
+    let usage_percent = (used_mb as f32 / total_mb.max(1) as f32) * 100.0;
+
+    Ok(MemoryInfo {
+        total_mb,
+        available_mb,
+        used_mb,
+        cache_mb,
+        usage_percent,
+        page_cache_mb,
+        buffers_mb: None,
+        reclaimable_slab_mb: None,
+        shared_mb: None,
+        standby_mb: lists.map(|(standby_mb, _)| standby_mb),
+        modified_mb: lists.map(|(_, modified_mb)| modified_mb),
+    })
+}
@@ -0,0 +1,322 @@
+// Threshold modes and evaluating a MemoryInfo sample against them
+
+use crate::error::{Error, Result};
+use crate::{Config, MemoryInfo};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// How `Config` thresholds are interpreted.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdMode {
+    /// Clean once cache reaches `start_threshold_mb`, stop at
+    /// `stop_threshold_mb`.
+    #[default]
+    AbsoluteMb,
+    /// Same, with `start_threshold_percent`/`stop_threshold_percent` of
+    /// total memory, so one profile fits machines of any size.
+    PercentOfTotal,
+    /// Clean once available memory drops below `start_threshold_mb`, stop
+    /// when it is back at `stop_threshold_mb` (so stop is above start).
+    AvailableBelowMb,
+}
+
+impl ThresholdMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "absolute_mb" => Ok(ThresholdMode::AbsoluteMb),
+            "percent_of_total" => Ok(ThresholdMode::PercentOfTotal),
+            "available_below_mb" => Ok(ThresholdMode::AvailableBelowMb),
+            _ => Err(Error::invalid_input(format!(
+                "Unknown threshold mode: {} (expected absolute_mb, percent_of_total or available_below_mb)",
+                value
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for ThresholdMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ThresholdMode::AbsoluteMb => "absolute_mb",
+            ThresholdMode::PercentOfTotal => "percent_of_total",
+            ThresholdMode::AvailableBelowMb => "available_below_mb",
+        })
+    }
+}
+
+/// Where a sample sits relative to the configured thresholds.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Evaluation {
+    /// The start threshold has been crossed
+    pub should_start: bool,
+    /// The stop threshold has been reached
+    pub should_stop: bool,
+    /// MB that would have to be freed to reach the stop threshold
+    pub target_mb: u64,
+}
+
+fn percent_of(total_mb: u64, percent: f32) -> u64 {
+    (total_mb as f64 * percent.clamp(0.0, 100.0) as f64 / 100.0) as u64
+}
+
+impl Config {
+    /// Start and stop thresholds in MB for a machine with `total_mb`.
+    pub fn thresholds_mb(&self, total_mb: u64) -> (u64, u64) {
+        match self.threshold_mode {
+            ThresholdMode::AbsoluteMb | ThresholdMode::AvailableBelowMb => {
+                (self.start_threshold_mb, self.stop_threshold_mb)
+            }
+            ThresholdMode::PercentOfTotal => (
+                percent_of(total_mb, self.start_threshold_percent),
+                percent_of(total_mb, self.stop_threshold_percent),
+            ),
+        }
+    }
+
+    /// MB between the two thresholds; the default amount for a manual clean.
+    pub fn band_mb(&self, total_mb: u64) -> u64 {
+        let (start_mb, stop_mb) = self.thresholds_mb(total_mb);
+        start_mb.abs_diff(stop_mb)
+    }
+}
+
+impl MemoryInfo {
+    pub fn evaluate(&self, config: &Config) -> Evaluation {
+        let (start_mb, stop_mb) = config.thresholds_mb(self.total_mb);
+
+        match config.threshold_mode {
+            ThresholdMode::AbsoluteMb | ThresholdMode::PercentOfTotal => Evaluation {
+                should_start: self.cache_mb >= start_mb,
+                should_stop: self.cache_mb <= stop_mb,
+                target_mb: self.cache_mb.saturating_sub(stop_mb),
+            },
+            ThresholdMode::AvailableBelowMb => Evaluation {
+                should_start: self.available_mb < start_mb,
+                should_stop: self.available_mb >= stop_mb,
+                target_mb: stop_mb.saturating_sub(self.available_mb),
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Threshold {
+    Start,
+    Stop,
+}
+
+/// A threshold condition that started or stopped holding between two
+/// samples.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crossing {
+    pub threshold: Threshold,
+    /// The condition now holds: a clean is due (start) or enough has been
+    /// freed (stop)
+    pub reached: bool,
+    /// Cache MB, or available MB in `available_below_mb` mode
+    pub value_mb: u64,
+    pub threshold_mb: u64,
+}
+
+/// Reports threshold crossings from a stream of samples.
+#[derive(Default, Debug, Clone)]
+pub struct CrossingWatcher {
+    last: Option<Evaluation>,
+}
+
+impl CrossingWatcher {
+    /// Feed a sample; the first one only sets the baseline.
+    pub fn update(&mut self, config: &Config, info: &MemoryInfo) -> Vec<Crossing> {
+        let evaluation = info.evaluate(config);
+        let last = self.last.replace(evaluation);
+        let Some(last) = last else {
+            return Vec::new();
+        };
+
+        let (start_mb, stop_mb) = config.thresholds_mb(info.total_mb);
+        let value_mb = match config.threshold_mode {
+            ThresholdMode::AbsoluteMb | ThresholdMode::PercentOfTotal => info.cache_mb,
+            ThresholdMode::AvailableBelowMb => info.available_mb,
+        };
+        let mut crossings = Vec::new();
+        if evaluation.should_start != last.should_start {
+            crossings.push(Crossing {
+                threshold: Threshold::Start,
+                reached: evaluation.should_start,
+                value_mb,
+                threshold_mb: start_mb,
+            });
+        }
+        if evaluation.should_stop != last.should_stop {
+            crossings.push(Crossing {
+                threshold: Threshold::Stop,
+                reached: evaluation.should_stop,
+                value_mb,
+                threshold_mb: stop_mb,
+            });
+        }
+        crossings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::tests::memory;
+
+    fn evaluation(should_start: bool, should_stop: bool, target_mb: u64) -> Evaluation {
+        Evaluation {
+            should_start,
+            should_stop,
+            target_mb,
+        }
+    }
+
+    #[test]
+    fn evaluates_cache_against_absolute_thresholds() {
+        let config = Config::default();
+        let evaluate = |cache_mb| memory(8192, 4096, cache_mb).evaluate(&config);
+        assert_eq!(evaluate(3000), evaluation(true, false, 1976));
+        assert_eq!(evaluate(2048), evaluation(true, false, 1024));
+        assert_eq!(evaluate(2047), evaluation(false, false, 1023));
+        assert_eq!(evaluate(1024), evaluation(false, true, 0));
+        assert_eq!(evaluate(500), evaluation(false, true, 0));
+    }
+
+    #[test]
+    fn evaluates_cache_against_percent_thresholds() {
+        let config = Config {
+            threshold_mode: ThresholdMode::PercentOfTotal,
+            start_threshold_percent: 25.0,
+            stop_threshold_percent: 12.5,
+            ..Config::default()
+        };
+        // 25% and 12.5% of 16000 MB
+        let evaluate = |cache_mb| memory(16000, 8000, cache_mb).evaluate(&config);
+        assert_eq!(evaluate(4000), evaluation(true, false, 2000));
+        assert_eq!(evaluate(3999), evaluation(false, false, 1999));
+        assert_eq!(evaluate(2000), evaluation(false, true, 0));
+    }
+
+    #[test]
+    fn percent_thresholds_round_down_to_whole_mb() {
+        let config = Config {
+            threshold_mode: ThresholdMode::PercentOfTotal,
+            start_threshold_percent: 25.0,
+            stop_threshold_percent: 12.5,
+            ..Config::default()
+        };
+        // 1001.75 and 500.875 MB
+        assert_eq!(config.thresholds_mb(4007), (1001, 500));
+        assert_eq!(config.band_mb(4007), 501);
+        let evaluate = |cache_mb| memory(4007, 2000, cache_mb).evaluate(&config);
+        assert_eq!(evaluate(1001), evaluation(true, false, 501));
+        assert_eq!(evaluate(500), evaluation(false, true, 0));
+        assert_eq!(evaluate(501), evaluation(false, false, 1));
+
+        let out_of_range = Config {
+            start_threshold_percent: 150.0,
+            stop_threshold_percent: -5.0,
+            ..config
+        };
+        assert_eq!(out_of_range.thresholds_mb(4007), (4007, 0));
+    }
+
+    #[test]
+    fn evaluates_available_memory_in_available_mode() {
+        let config = Config {
+            threshold_mode: ThresholdMode::AvailableBelowMb,
+            start_threshold_mb: 1024,
+            stop_threshold_mb: 2048,
+            ..Config::default()
+        };
+        // Cache does not matter in this mode
+        let evaluate = |available_mb| memory(8192, available_mb, 6000).evaluate(&config);
+        assert_eq!(evaluate(500), evaluation(true, false, 1548));
+        assert_eq!(evaluate(1023), evaluation(true, false, 1025));
+        assert_eq!(evaluate(1024), evaluation(false, false, 1024));
+        assert_eq!(evaluate(2048), evaluation(false, true, 0));
+        assert_eq!(evaluate(4000), evaluation(false, true, 0));
+        assert_eq!(config.band_mb(8192), 1024);
+    }
+
+    #[test]
+    fn crossing_watcher_sets_a_baseline_first() {
+        let config = Config::default();
+        let mut watcher = CrossingWatcher::default();
+        assert!(watcher
+            .update(&config, &memory(8192, 4096, 3000))
+            .is_empty());
+    }
+
+    #[test]
+    fn crossing_watcher_reports_each_edge_once() {
+        let config = Config::default();
+        let mut watcher = CrossingWatcher::default();
+        let mut update = |cache_mb| watcher.update(&config, &memory(8192, 4096, cache_mb));
+
+        assert!(update(1500).is_empty());
+        assert_eq!(
+            update(2100),
+            [Crossing {
+                threshold: Threshold::Start,
+                reached: true,
+                value_mb: 2100,
+                threshold_mb: 2048,
+            }]
+        );
+        assert!(update(2500).is_empty());
+        assert_eq!(
+            update(1000),
+            [
+                Crossing {
+                    threshold: Threshold::Start,
+                    reached: false,
+                    value_mb: 1000,
+                    threshold_mb: 2048,
+                },
+                Crossing {
+                    threshold: Threshold::Stop,
+                    reached: true,
+                    value_mb: 1000,
+                    threshold_mb: 1024,
+                },
+            ]
+        );
+        assert!(update(900).is_empty());
+        assert_eq!(
+            update(1100),
+            [Crossing {
+                threshold: Threshold::Stop,
+                reached: false,
+                value_mb: 1100,
+                threshold_mb: 1024,
+            }]
+        );
+    }
+
+    #[test]
+    fn crossing_watcher_reports_available_memory_in_available_mode() {
+        let config = Config {
+            threshold_mode: ThresholdMode::AvailableBelowMb,
+            start_threshold_mb: 1024,
+            stop_threshold_mb: 2048,
+            ..Config::default()
+        };
+        let mut watcher = CrossingWatcher::default();
+        assert!(watcher
+            .update(&config, &memory(8192, 1500, 3000))
+            .is_empty());
+        assert_eq!(
+            watcher.update(&config, &memory(8192, 900, 3000)),
+            [Crossing {
+                threshold: Threshold::Start,
+                reached: true,
+                value_mb: 900,
+                threshold_mb: 1024,
+            }]
+        );
+    }
+}
@@ -1,156 +1,586 @@
 // Prevents additional console window on Windows in release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use serde::{Deserialize, Serialize};
-use std::sync::Mutex;
-use tauri::State;
-
-#[cfg(target_os = "windows")]
-use windows::Win32::Foundation::*;
-#[cfg(target_os = "windows")]
-use windows::Win32::System::Memory::*;
-#[cfg(target_os = "windows")]
-use windows::Win32::System::ProcessStatus::*;
-#[cfg(target_os = "windows")]
-use windows::Win32::System::SystemInformation::*;
-#[cfg(target_os = "windows")]
-use windows::Win32::System::Threading::*;
-
-#[derive(Default)]
+use memory_cache_core::clean::{CancelToken, CleanPlan, CleanReport, Progress, Strategy};
+use memory_cache_core::config::ConfigStore;
+use memory_cache_core::export::{self, Dataset, ExportFormat, ExportOptions, Unit};
+use memory_cache_core::exporter::{self, MetricsServer};
+use memory_cache_core::history::{now_ms, CleanMarker, HistoryOptions, HistoryRange};
+use memory_cache_core::metrics_store::MetricsStore;
+use memory_cache_core::monitor::{Monitor, MonitorEvent};
+use memory_cache_core::process::trim::{self, trim_processes};
+use memory_cache_core::process::{
+    self, ProcessDecision, ProcessInfo, ProcessQuery, ProcessTarget, TrimMethod, TrimReport,
+};
+use memory_cache_core::scheduler::{SchedulerEvent, SchedulerOptions};
+use memory_cache_core::{
+    clean, source, Config, Error, History, MemoryInfo, MemorySource, Result, Scheduler,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use tauri::menu::{Menu, MenuItem};
+use tauri::tray::TrayIconBuilder;
+use tauri::{AppHandle, Emitter, Manager, State, WindowEvent};
+
 struct AppState {
-    config: Mutex<Config>,
+    config: Arc<Mutex<Config>>,
+    store: Option<ConfigStore>,
+    source: Arc<dyn MemorySource>,
+    // Filled by the scheduler's samples and every clean
+    history: Arc<Mutex<History>>,
+    // Held for the duration of a clean, manual or automatic
+    clean_lock: Arc<Mutex<()>>,
+    // Running manual cleans by job id
+    jobs: Arc<Mutex<HashMap<u64, CancelToken>>>,
+    next_job_id: AtomicU64,
+    // Serves /metrics while `metrics_port` is set
+    metrics: Mutex<Option<MetricsServer>>,
+    // Auto-clean keeps running while the window is hidden in the tray;
+    // `None` if its thread could not be started
+    _scheduler: Option<Scheduler>,
+    // Pushes `memory://` events to the window; `None` if its thread could
+    // not be started
+    _monitor: Option<Monitor>,
+}
+
+impl AppState {
+    fn new(app: &AppHandle, source: Arc<dyn MemorySource>, store: Option<ConfigStore>) -> Self {
+        let config = match &store {
+            Some(store) => store.load().unwrap_or_else(|e| {
+                eprintln!("{}, using default config", e);
+                Config::default()
+            }),
+            None => Config::default(),
+        };
+
+        let metrics_port = config.metrics_port;
+        let config = Arc::new(Mutex::new(config));
+        let history = match MetricsStore::default_location() {
+            Ok(store) => History::with_store(HistoryOptions::default(), store),
+            Err(e) => {
+                eprintln!("{}, memory history will not be saved", e);
+                History::default()
+            }
+        };
+        let history = Arc::new(Mutex::new(history));
+        let clean_lock = Arc::new(Mutex::new(()));
+        let scheduler = Scheduler::start(
+            Arc::clone(&source),
+            Arc::clone(&config),
+            Arc::clone(&history),
+            {
+                let app = app.clone();
+                let source = Arc::clone(&source);
+                let clean_lock = Arc::clone(&clean_lock);
+                Box::new(move |config: &Config, target_mb| {
+                    clean::check_process_rules(
+                        &config.strategy,
+                        &config.process_rules,
+                        &*process::native_processes(),
+                    )?;
+                    let _cleaning = lock(&clean_lock);
+                    let started = CleanStartedEvent {
+                        job_id: None,
+                        automatic: true,
+                        strategy: config.strategy.clone(),
+                        target_mb,
+                    };
+                    let _ = app.emit("clean://started", started);
+                    let result =
+                        clean::clean_memory_cache(&config.strategy, target_mb, config, &*source);
+                    let _ = app.emit("clean://finished", CleanFinishedEvent::new(None, &result));
+                    result.map(|report| report.cleaned_mb)
+                })
+            },
+            {
+                let app = app.clone();
+                Box::new(move |event| match event {
+                    SchedulerEvent::CleanFailed(e) => {
+                        let _ = app.emit("auto-clean://error", e);
+                    }
+                    SchedulerEvent::HistoryFailed(e) => {
+                        let _ = app.emit("history://error", e);
+                    }
+                    // The monitor reports the same source to the window
+                    SchedulerEvent::SourceFailed(_) => {}
+                })
+            },
+            SchedulerOptions::default(),
+        )
+        .map_err(|e| eprintln!("{}, automatic cleaning is disabled", e))
+        .ok();
+        let monitor = Monitor::start(Arc::clone(&source), Arc::clone(&config), {
+            let app = app.clone();
+            Box::new(move |event| {
+                let _ = match event {
+                    MonitorEvent::Update(sample) => app.emit("memory://update", sample),
+                    MonitorEvent::ThresholdCrossed(crossing) => {
+                        app.emit("memory://threshold", crossing)
+                    }
+                    MonitorEvent::SourceFailed(e) => app.emit("memory://error", e),
+                };
+            })
+        })
+        .map_err(|e| eprintln!("{}, live updates are disabled", e))
+        .ok();
+
+        let metrics = metrics_port.and_then(|port| {
+            start_metrics(app, port, &source, &config, &history)
+                .map_err(|e| eprintln!("{}, metrics will not be served", e))
+                .ok()
+        });
+
+        Self {
+            config,
+            store,
+            source,
+            history,
+            clean_lock,
+            jobs: Arc::new(Mutex::new(HashMap::new())),
+            next_job_id: AtomicU64::new(1),
+            metrics: Mutex::new(metrics),
+            _scheduler: scheduler,
+            _monitor: monitor,
+        }
+    }
+}
+
+#[derive(Serialize, Clone)]
+struct CleanProgressEvent {
+    job_id: u64,
+    #[serde(flatten)]
+    progress: Progress,
+}
+
+/// `job_id` is `None` for cleans started by the scheduler.
+#[derive(Serialize, Clone)]
+struct CleanStartedEvent {
+    job_id: Option<u64>,
+    automatic: bool,
+    strategy: Strategy,
+    target_mb: u64,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct Config {
-    start_threshold_mb: u64,
-    stop_threshold_mb: u64,
-    auto_clean_enabled: bool,
+/// Exactly one of `report` and `error` is set.
+#[derive(Serialize, Clone)]
+struct CleanFinishedEvent {
+    job_id: Option<u64>,
+    automatic: bool,
+    report: Option<CleanReport>,
+    error: Option<Error>,
 }
 
-impl Default for Config {
-    fn default() -> Self {
+impl CleanFinishedEvent {
+    fn new(job_id: Option<u64>, result: &Result<CleanReport>) -> Self {
+        let (report, error) = match result {
+            Ok(report) => (Some(report.clone()), None),
+            Err(e) => (None, Some(e.clone())),
+        };
         Self {
-            start_threshold_mb: 2048,
-            stop_threshold_mb: 1024,
-            auto_clean_enabled: true,
+            job_id,
+            automatic: job_id.is_none(),
+            report,
+            error,
         }
     }
 }
 
-#[derive(Serialize)]
-struct MemoryInfo {
-    total_mb: u64,
-    available_mb: u64,
-    used_mb: u64,
-    cache_mb: u64,
-    usage_percent: f32,
+/// Serve the current memory reading, config and clean totals on `port`.
+/// Failed requests are reported to the window.
+fn start_metrics(
+    app: &AppHandle,
+    port: u16,
+    source: &Arc<dyn MemorySource>,
+    config: &Arc<Mutex<Config>>,
+    history: &Arc<Mutex<History>>,
+) -> Result<MetricsServer> {
+    let source = Arc::clone(source);
+    let config = Arc::clone(config);
+    let history = Arc::clone(history);
+    let app = app.clone();
+    MetricsServer::start(
+        port,
+        move || {
+            let info = source.read().ok();
+            let config = lock(&config).clone();
+            let stats = lock(&history).clean_stats().clone();
+            exporter::render(info.as_ref(), &config, &stats)
+        },
+        move |e| {
+            let _ = app.emit("metrics://error", e);
+        },
+    )
+}
+
+/// Lock, recovering from a panic in another holder rather than crashing.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
 }
 
-#[cfg(target_os = "windows")]
 #[tauri::command]
-fn get_memory_info() -> Result<MemoryInfo, String> {
-    unsafe {
-        let mut mem_status: MEMORYSTATUSEX = std::mem::zeroed();
-        mem_status.dwLength = std::mem::size_of::<MEMORYSTATUSEX>() as u32;
+fn get_memory_info(state: State<AppState>) -> Result<MemoryInfo> {
+    state.source.read()
+}
 
-        if GlobalMemoryStatusEx(&mut mem_status).is_err() {
-            return Err("Failed to get memory status".to_string());
-        }
+/// Per-process memory, sorted and filtered by `query` (default: all
+/// processes, largest RSS first). Reads every process's smaps_rollup, so
+/// it runs off the main thread.
+#[tauri::command(async)]
+fn list_processes(query: Option<ProcessQuery>) -> Result<Vec<ProcessInfo>> {
+    process::list_processes(&*process::native_processes(), &query.unwrap_or_default())
+}
 
-        let total_mb = mem_status.ullTotalPhys / (1024 * 1024);
-        let available_mb = mem_status.ullAvailPhys / (1024 * 1024);
-        let used_mb = total_mb - available_mb;
-        
-        // Estimate cache: typically 40-60% of used memory
-        let cache_mb = (used_mb as f32 * 0.5) as u64;
-        let usage_percent = ((used_mb as f32 / total_mb as f32) * 100.0);
-
-        Ok(MemoryInfo {
-            total_mb,
-            available_mb,
-            used_mb,
-            cache_mb,
-            usage_percent,
-        })
+/// Trim the process with `pid`, or every process whose name matches the
+/// glob `name`. Per-process failures are reported in the result.
+#[tauri::command(async)]
+fn trim_process(
+    state: State<'_, AppState>,
+    pid: Option<u32>,
+    name: Option<String>,
+    method: Option<TrimMethod>,
+) -> Result<TrimReport> {
+    let target = process_target(pid, name)?;
+    let rules = lock(&state.config).process_rules.clone();
+    trim_processes(
+        &*process::native_processes(),
+        &target,
+        method.unwrap_or_default(),
+        &rules,
+    )
+}
+
+fn process_target(pid: Option<u32>, name: Option<String>) -> Result<ProcessTarget> {
+    match (pid, name) {
+        (Some(pid), None) => Ok(ProcessTarget::Pid(pid)),
+        (None, Some(name)) => Ok(ProcessTarget::Name(name)),
+        _ => Err(Error::invalid_input("Pass exactly one of pid and name")),
     }
 }
 
-#[cfg(not(target_os = "windows"))]
+/// Processes a trim of `pid`/`name` with `method` would touch, or else
+/// those a clean with `strategy` (default: the configured one) would act
+/// on, each with the rule that excludes it, if any.
+#[tauri::command(async)]
+fn get_affected_processes(
+    state: State<'_, AppState>,
+    strategy: Option<Strategy>,
+    pid: Option<u32>,
+    name: Option<String>,
+    method: Option<TrimMethod>,
+) -> Result<Vec<ProcessDecision>> {
+    let config = lock(&state.config).clone();
+    let source = process::native_processes();
+    if pid.is_some() || name.is_some() {
+        let target = process_target(pid, name)?;
+        return trim::preview(
+            &*source,
+            &target,
+            method.unwrap_or_default(),
+            &config.process_rules,
+        );
+    }
+    let strategy = strategy.unwrap_or(config.strategy);
+    clean::affected_processes(&strategy, &config.process_rules, &*source)
+}
+
+/// Samples from the last `range_secs`, averaged into `resolution_secs`
+/// buckets (default: about 240 points over the range).
 #[tauri::command]
-fn get_memory_info() -> Result<MemoryInfo, String> {
-    Err("Only supported on Windows".to_string())
+fn get_memory_history(
+    state: State<AppState>,
+    range_secs: u64,
+    resolution_secs: Option<u64>,
+) -> Result<HistoryRange> {
+    if range_secs == 0 {
+        return Err(Error::invalid_input("range_secs must be greater than 0"));
+    }
+    let resolution_ms = match resolution_secs {
+        Some(resolution_secs) => resolution_secs.saturating_mul(1000),
+        None => range_secs.saturating_mul(1000) / 240,
+    };
+    // Values come straight from the window; a huge range means everything
+    let now = now_ms();
+    let from = now.saturating_sub(range_secs.saturating_mul(1000));
+    lock(&state.history).query(from, now + 1, resolution_ms)
 }
 
-#[cfg(target_os = "windows")]
+/// Export the last `range_secs` of history at full stored resolution as a
+/// string; the window saves it.
 #[tauri::command]
-fn clean_memory_cache(target_mb: u64) -> Result<u64, String> {
-    unsafe {
-        let mut cleaned_mb: u64 = 0;
-        let chunk_size = 100 * 1024 * 1024; // 100MB chunks
-        let max_iterations = (target_mb * 1024 * 1024) / chunk_size;
-
-        // Method 1: Force memory to be paged out by allocating and freeing
-        for _ in 0..max_iterations {
-            let ptr = VirtualAlloc(
-                None,
-                chunk_size,
-                MEM_COMMIT | MEM_RESERVE,
-                PAGE_READWRITE,
-            );
+fn export_history(
+    state: State<AppState>,
+    range_secs: u64,
+    format: Option<String>,
+    dataset: Option<String>,
+    unit: Option<String>,
+    columns: Option<Vec<String>>,
+) -> Result<String> {
+    if range_secs == 0 {
+        return Err(Error::invalid_input("range_secs must be greater than 0"));
+    }
+    let options = ExportOptions {
+        format: format
+            .as_deref()
+            .map(ExportFormat::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        dataset: dataset
+            .as_deref()
+            .map(Dataset::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        unit: unit
+            .as_deref()
+            .map(Unit::parse)
+            .transpose()?
+            .unwrap_or_default(),
+        columns,
+    };
+    let now = now_ms();
+    let from = now.saturating_sub(range_secs.saturating_mul(1000));
+    let range = lock(&state.history).query(from, now + 1, 0)?;
+    export::export(&range, &options)
+}
 
-            if !ptr.is_null() {
-                // Write to memory to ensure it's committed
-                std::ptr::write_bytes(ptr as *mut u8, 0, chunk_size);
-                
-                // Free immediately
-                let _ = VirtualFree(ptr, 0, MEM_RELEASE);
-                cleaned_mb += 100;
-            } else {
-                break;
-            }
+/// What `clean_memory_cache` returns: the job id of a started clean, or
+/// the plan for a dry run.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum CleanResponse {
+    Job(u64),
+    Plan(Box<CleanPlan>),
+}
+
+/// Start a clean on a background thread and return its job id. Emits
+/// `clean://started`, then `clean://progress` and `clean://finished`.
+/// With `dry_run`, returns the plan instead and touches no memory. The
+/// process rules check lists every process, so this runs off the main
+/// thread.
+#[tauri::command(async)]
+fn clean_memory_cache(
+    app: AppHandle,
+    state: State<'_, AppState>,
+    target_mb: Option<u64>,
+    strategy: Option<Strategy>,
+    dry_run: Option<bool>,
+) -> Result<CleanResponse> {
+    let config = lock(&state.config).clone();
+    let target_mb = match target_mb {
+        Some(target_mb) => target_mb,
+        None => config.band_mb(state.source.read()?.total_mb),
+    };
+    let strategy = strategy.unwrap_or_else(|| config.strategy.clone());
+    if dry_run.unwrap_or(false) {
+        let plan = clean::plan_clean(
+            &strategy,
+            target_mb,
+            &config,
+            &*state.source,
+            &*process::native_processes(),
+        )?;
+        return Ok(CleanResponse::Plan(Box::new(plan)));
+    }
+    clean::check_process_rules(
+        &strategy,
+        &config.process_rules,
+        &*process::native_processes(),
+    )?;
 
-            // Small delay to not overwhelm system
-            std::thread::sleep(std::time::Duration::from_millis(10));
+    let job_id = state.next_job_id.fetch_add(1, Ordering::Relaxed);
+    let cancel = CancelToken::new();
+    {
+        let mut jobs = lock(&state.jobs);
+        let auto_cleaning = matches!(state.clean_lock.try_lock(), Err(TryLockError::WouldBlock));
+        if !jobs.is_empty() || auto_cleaning {
+            return Err(Error::busy("A clean is already running"));
         }
+        jobs.insert(job_id, cancel.clone());
+    }
 
-        // Method 2: Clear working set of current process
-        let process = GetCurrentProcess();
-        let _ = EmptyWorkingSet(process);
+    let started = CleanStartedEvent {
+        job_id: Some(job_id),
+        automatic: false,
+        strategy: strategy.clone(),
+        target_mb,
+    };
+    let source = Arc::clone(&state.source);
+    let history = Arc::clone(&state.history);
+    let clean_lock = Arc::clone(&state.clean_lock);
+    let jobs = Arc::clone(&state.jobs);
+    let spawned = std::thread::Builder::new()
+        .name(format!("clean-{}", job_id))
+        .spawn(move || {
+            let result = {
+                let _cleaning = lock(&clean_lock);
+                let _ = app.emit("clean://started", started);
+                clean::clean_with_progress(
+                    &strategy,
+                    target_mb,
+                    &config,
+                    &*source,
+                    &cancel,
+                    &mut |progress| {
+                        let event = CleanProgressEvent {
+                            job_id,
+                            progress: *progress,
+                        };
+                        let _ = app.emit("clean://progress", event);
+                    },
+                )
+            };
+            lock(&jobs).remove(&job_id);
+            if let Ok(report) = &result {
+                let recorded = lock(&history).record_clean(CleanMarker {
+                    timestamp_ms: now_ms(),
+                    cleaned_mb: report.cleaned_mb,
+                    automatic: false,
+                    duration_ms: report.duration_ms,
+                });
+                if let Err(e) = recorded {
+                    let _ = app.emit("history://error", e);
+                }
+            }
+            let _ = app.emit(
+                "clean://finished",
+                CleanFinishedEvent::new(Some(job_id), &result),
+            );
+        });
 
-        Ok(cleaned_mb)
+    if let Err(e) = spawned {
+        lock(&state.jobs).remove(&job_id);
+        return Err(Error::io("Failed to start clean", e));
     }
+    Ok(CleanResponse::Job(job_id))
 }
 
-#[cfg(not(target_os = "windows"))]
+/// Ask a running clean to stop; it finishes with a `cancelled` error at the
+/// next step or chunk.
 #[tauri::command]
-fn clean_memory_cache(_target_mb: u64) -> Result<u64, String> {
-    Err("Only supported on Windows".to_string())
+fn cancel_clean(state: State<AppState>, job_id: u64) -> Result<()> {
+    match lock(&state.jobs).get(&job_id) {
+        Some(cancel) => {
+            cancel.cancel();
+            Ok(())
+        }
+        None => Err(Error::invalid_input(format!(
+            "No running clean with id {}",
+            job_id
+        ))),
+    }
 }
 
 #[tauri::command]
-fn save_config(state: State<AppState>, config: Config) -> Result<(), String> {
-    let mut app_config = state.config.lock().unwrap();
-    *app_config = config;
-    Ok(())
+fn save_config(
+    app: AppHandle,
+    state: State<AppState>,
+    config: Config,
+    normalize: Option<bool>,
+) -> Result<Config> {
+    let total_mb = state.source.read().ok().map(|info| info.total_mb);
+    let config = if normalize.unwrap_or(false) {
+        config.normalized(total_mb)
+    } else {
+        config
+    };
+    config.validate(total_mb)?;
+
+    // Bind the new port before saving so a port in use is reported here
+    let mut metrics = lock(&state.metrics);
+    let port_changed = metrics.as_ref().map(|server| server.addr().port()) != config.metrics_port;
+    let new_metrics = match config.metrics_port {
+        Some(port) if port_changed => Some(start_metrics(
+            &app,
+            port,
+            &state.source,
+            &state.config,
+            &state.history,
+        )?),
+        _ => None,
+    };
+
+    if let Some(store) = &state.store {
+        store.save(&config)?;
+    }
+    if port_changed {
+        *metrics = new_metrics;
+    }
+    let mut app_config = lock(&state.config);
+    *app_config = config.clone();
+    Ok(config)
 }
 
 #[tauri::command]
-fn load_config(state: State<AppState>) -> Result<Config, String> {
-    let config = state.config.lock().unwrap();
+fn load_config(state: State<AppState>) -> Result<Config> {
+    let mut config = lock(&state.config);
+    // Pick up changes made with `mcm config set` since startup
+    if let Some(store) = &state.store {
+        *config = store.load()?;
+    }
     Ok(config.clone())
 }
 
 fn main() {
+    let source: Arc<dyn MemorySource> = source::default_source()
+        .unwrap_or_else(|e| {
+            eprintln!("{}, falling back to the native memory source", e);
+            source::native_source()
+        })
+        .into();
+
+    let store = ConfigStore::default_location()
+        .map_err(|e| eprintln!("{}, config will not be saved", e))
+        .ok();
+
     tauri::Builder::default()
-        .manage(AppState::default())
         .plugin(tauri_plugin_shell::init())
+        .setup(move |app| {
+            // Managed here because the background threads emit through the handle
+            app.manage(AppState::new(app.handle(), source, store));
+
+            let show = MenuItem::with_id(app, "show", "Show", true, None::<&str>)?;
+            let quit = MenuItem::with_id(app, "quit", "Quit", true, None::<&str>)?;
+            let menu = Menu::with_items(app, &[&show, &quit])?;
+
+            let mut tray = TrayIconBuilder::new()
+                .tooltip("Memory Cache Manager")
+                .menu(&menu)
+                .on_menu_event(|app, event| match event.id.as_ref() {
+                    "show" => {
+                        if let Some(window) = app.get_webview_window("main") {
+                            let _ = window.show();
+                            let _ = window.set_focus();
+                        }
+                    }
+                    "quit" => app.exit(0),
+                    _ => {}
+                });
+            if let Some(icon) = app.default_window_icon() {
+                tray = tray.icon(icon.clone());
+            }
+            tray.build(app)?;
+            Ok(())
+        })
+        .on_window_event(|window, event| {
+            // Closing the window hides it to the tray; quit from the tray menu
+            if let WindowEvent::CloseRequested { api, .. } = event {
+                api.prevent_close();
+                let _ = window.hide();
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             get_memory_info,
+            get_memory_history,
+            export_history,
+            list_processes,
+            trim_process,
+            get_affected_processes,
             clean_memory_cache,
+            cancel_clean,
             save_config,
             load_config
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
-}
\ No newline at end of file
+}